@@ -0,0 +1,402 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use csv::ReaderBuilder;
+use encoding_rs::Encoding;
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColumnType {
+    Auto,
+    Int,
+    Float,
+    String,
+}
+
+impl ColumnType {
+    fn parse(value: &str) -> Self {
+        match value {
+            "auto" => ColumnType::Auto,
+            "int" => ColumnType::Int,
+            "float" => ColumnType::Float,
+            "string" => ColumnType::String,
+            _ => panic!("Unknown column type {}", value),
+        }
+    }
+
+    // looks at every non-null value of a column and picks the narrowest type that fits them all
+    fn infer<'a>(values: impl Iterator<Item = &'a str>) -> Self {
+        let mut column_type = ColumnType::Int;
+        for value in values {
+            // as a float a long numeric id would be silently rounded
+            if overflows_int(value) {
+                return ColumnType::String;
+            }
+            if column_type == ColumnType::Int && !looks_like_int(value) {
+                column_type = ColumnType::Float;
+            }
+            if column_type == ColumnType::Float && !looks_like_float(value) {
+                return ColumnType::String;
+            }
+        }
+        column_type
+    }
+}
+
+// values with leading zeros (zip codes, phone numbers) must not lose them
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.trim_start_matches('-');
+    digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+}
+
+fn looks_like_int(value: &str) -> bool {
+    !has_leading_zero(value) && value.parse::<i64>().is_ok()
+}
+
+fn overflows_int(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && value.parse::<i64>().is_err()
+}
+
+fn looks_like_float(value: &str) -> bool {
+    !has_leading_zero(value) && value.parse::<f64>().is_ok_and(|f| f.is_finite())
+}
+
+fn read_char_option(config: &Map<String, Value>, name: &str, default: u8) -> u8 {
+    match config.get(name) {
+        Some(value) => {
+            let value = value.as_str().unwrap();
+            if value.len() != 1 {
+                panic!("{} must be a single ascii character", name);
+            }
+            value.as_bytes()[0]
+        }
+        None => default,
+    }
+}
+
+pub fn decode(bytes: &[u8], encoding: &str) -> String {
+    let encoding = Encoding::for_label(encoding.as_bytes())
+        .unwrap_or_else(|| panic!("Unknown encoding {}", encoding));
+    let (content, _, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        eprintln!(
+            "warning: some characters could not be decoded as {}",
+            encoding.name()
+        );
+    }
+    content.into_owned()
+}
+
+pub struct InputCsv {
+    node_name: String,
+    path: String,
+    delimiter: u8,
+    quote: u8,
+    header: bool,
+    columns: Option<Vec<String>>,
+    encoding: String,
+    infer_types: bool,
+    types: HashMap<String, ColumnType>,
+    null_values: Vec<String>,
+}
+
+impl InputCsv {
+    fn column_names(&self, header: Option<Vec<String>>, width: usize) -> Vec<String> {
+        match (&self.columns, header) {
+            (Some(columns), _) => columns.clone(),
+            (None, Some(header)) => header,
+            (None, None) => (1..=width).map(|i| format!("column{}", i)).collect(),
+        }
+    }
+
+    fn convert(&self, column: &str, value: &str, column_type: ColumnType) -> Variant {
+        if self.null_values.iter().any(|n| n == value) {
+            return Variant::Null;
+        }
+        match column_type {
+            ColumnType::Int => Variant::Int(value.parse().unwrap_or_else(|_| {
+                panic!("column {}: value {} is not an integer", column, value)
+            })),
+            ColumnType::Float => {
+                Variant::Float(value.parse().unwrap_or_else(|_| {
+                    panic!("column {}: value {} is not a number", column, value)
+                }))
+            }
+            ColumnType::String | ColumnType::Auto => Variant::String(value.to_string()),
+        }
+    }
+
+    pub fn parse(&self, content: &str) -> (Vec<String>, Vec<Record>) {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+
+        let mut rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|row| row.unwrap().iter().map(|s| s.to_string()).collect())
+            .collect();
+        let header = if self.header && !rows.is_empty() {
+            Some(rows.remove(0))
+        } else {
+            None
+        };
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let columns = self.column_names(header, width);
+
+        let column_types: Vec<ColumnType> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let column_type = *self.types.get(column).unwrap_or(&ColumnType::Auto);
+                if column_type != ColumnType::Auto || !self.infer_types {
+                    return column_type;
+                }
+                ColumnType::infer(
+                    rows.iter()
+                        .filter_map(|row| row.get(i))
+                        .map(|s| s.as_str())
+                        .filter(|s| !self.null_values.iter().any(|n| n == s)),
+                )
+            })
+            .collect();
+
+        let records = rows
+            .iter()
+            .map(|row| {
+                let mut fields = IndexMap::new();
+                for (i, column) in columns.iter().enumerate() {
+                    let value = match row.get(i) {
+                        Some(value) => self.convert(column, value, column_types[i]),
+                        None => Variant::Null,
+                    };
+                    fields.insert(column.clone(), value);
+                }
+                Record { fields }
+            })
+            .collect();
+        (columns, records)
+    }
+}
+
+impl Process for InputCsv {
+    register_process!(input::csv);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let columns = config.get("columns").map(|columns| {
+            columns
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c.as_str().unwrap().to_string())
+                .collect()
+        });
+        let types = config
+            .get("types")
+            .unwrap_or(&Value::Null)
+            .as_object()
+            .unwrap_or(&Map::new())
+            .iter()
+            .map(|(k, v)| (k.clone(), ColumnType::parse(v.as_str().unwrap())))
+            .collect();
+        let null_values = match config.get("null_values") {
+            Some(values) => values
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_str().unwrap().to_string())
+                .collect(),
+            None => vec!["".to_string()],
+        };
+        InputCsv {
+            node_name,
+            path: read_config_field(&config, "path"),
+            delimiter: read_char_option(&config, "delimiter", b','),
+            quote: read_char_option(&config, "quote", b'"'),
+            header: config
+                .get("header")
+                .unwrap_or(&Value::Bool(true))
+                .as_bool()
+                .unwrap(),
+            columns,
+            encoding: config
+                .get("encoding")
+                .and_then(|e| e.as_str())
+                .unwrap_or("utf-8")
+                .to_string(),
+            infer_types: config
+                .get("infer_types")
+                .unwrap_or(&Value::Bool(true))
+                .as_bool()
+                .unwrap(),
+            types,
+            null_values,
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let bytes = state.read_file_bytes(self.path.as_str());
+        let content = decode(&bytes, self.encoding.as_str());
+        let (columns, records) = self.parse(content.as_str());
+
+        state.tables.push(Table {
+            name: self.node_name.clone(),
+            records,
+            columns,
+            schema: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Config, MemoryReader, MemoryWriter};
+
+    fn make_input(config: &str) -> InputCsv {
+        let config: Value = serde_json::from_str(config).unwrap();
+        InputCsv::from_config("people".to_string(), config.as_object().unwrap().clone())
+    }
+
+    fn run_input(config: &str, lines: &[&str]) -> State {
+        let config_struct = Config {
+            state_file: "state.json".to_string(),
+            template_file: "template.json".to_string(),
+        };
+        let pipeline = serde_json::from_str(r#"{"people":{"driver":"input::csv"}}"#).unwrap();
+        let mut reader = MemoryReader::new();
+        reader.files.insert(
+            "people.csv".to_string(),
+            lines.iter().map(|l| l.to_string()).collect(),
+        );
+        let mut state = State::make(
+            &config_struct,
+            &pipeline,
+            Some(Box::new(MemoryWriter::new())),
+            Some(Box::new(reader)),
+        );
+        make_input(config).run(&mut state);
+        state
+    }
+
+    #[test]
+    fn test_header_and_type_inference() {
+        let state = run_input(
+            r#"{"path":"people.csv"}"#,
+            &[
+                "name,age,height,zip",
+                "Alice,30,1.65,01234",
+                "Bob,,1.8,20100",
+            ],
+        );
+        let table = state.find_table("people").unwrap();
+        assert_eq!(table.records.len(), 2);
+        let alice = &table.records[0].fields;
+        assert_eq!(alice["name"], Variant::String("Alice".to_string()));
+        assert_eq!(alice["age"], Variant::Int(30));
+        assert_eq!(alice["height"], Variant::Float(1.65));
+        assert_eq!(alice["zip"], Variant::String("01234".to_string()));
+        assert_eq!(table.records[1].fields["age"], Variant::Null);
+    }
+
+    #[test]
+    fn test_columns_in_file_order() {
+        let state = run_input(
+            r#"{"path":"people.csv"}"#,
+            &["zip,name,age", "01234,Alice,30"],
+        );
+        let table = state.find_table("people").unwrap();
+        assert_eq!(table.columns, vec!["zip", "name", "age"]);
+        let fields: Vec<&String> = table.records[0].fields.keys().collect();
+        assert_eq!(fields, vec!["zip", "name", "age"]);
+
+        // a file with just the header still has its columns
+        let state = run_input(r#"{"path":"people.csv"}"#, &["zip,name,age"]);
+        let table = state.find_table("people").unwrap();
+        assert!(table.records.is_empty());
+        assert_eq!(table.column_names(), vec!["zip", "name", "age"]);
+    }
+
+    #[test]
+    fn test_mixed_int_and_float_column_is_float() {
+        let input = make_input(r#"{"path":"x"}"#);
+        let (_, records) = input.parse("v\n1\n2.5\n");
+        assert_eq!(records[0].fields["v"], Variant::Float(1.0));
+        assert_eq!(records[1].fields["v"], Variant::Float(2.5));
+    }
+
+    #[test]
+    fn test_int_overflow_column_is_string() {
+        let input = make_input(r#"{"path":"x"}"#);
+        let (_, records) = input.parse("id\n12345678901234567890\n7\n");
+        assert_eq!(
+            records[0].fields["id"],
+            Variant::String("12345678901234567890".to_string())
+        );
+        assert_eq!(records[1].fields["id"], Variant::String("7".to_string()));
+    }
+
+    #[test]
+    fn test_delimiter_quote_and_explicit_columns() {
+        let input = make_input(
+            r#"{"path":"x","delimiter":";","quote":"'","header":false,"columns":["id","note"]}"#,
+        );
+        let (_, records) = input.parse("1;'semi; colon'\n2;'it''s'\n");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields["id"], Variant::Int(1));
+        assert_eq!(
+            records[0].fields["note"],
+            Variant::String("semi; colon".to_string())
+        );
+        assert_eq!(
+            records[1].fields["note"],
+            Variant::String("it's".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quoted_newlines() {
+        let state = run_input(
+            r#"{"path":"people.csv"}"#,
+            &["name,bio", "Alice,\"line one", "line two\""],
+        );
+        let table = state.find_table("people").unwrap();
+        assert_eq!(table.records.len(), 1);
+        assert_eq!(
+            table.records[0].fields["bio"],
+            Variant::String("line one\nline two".to_string())
+        );
+    }
+
+    #[test]
+    fn test_explicit_types_and_null_values() {
+        let input = make_input(
+            r#"{"path":"x","types":{"code":"string"},"null_values":["NULL"],"infer_types":true}"#,
+        );
+        let (_, records) = input.parse("code,qty\n42,NULL\n43,7\n");
+        assert_eq!(records[0].fields["code"], Variant::String("42".to_string()));
+        assert_eq!(records[0].fields["qty"], Variant::Null);
+        assert_eq!(records[1].fields["qty"], Variant::Int(7));
+    }
+
+    #[test]
+    fn test_no_inference_and_generated_column_names() {
+        let input = make_input(r#"{"path":"x","header":false,"infer_types":false}"#);
+        let (_, records) = input.parse("1,2\n3\n");
+        assert_eq!(
+            records[0].fields["column1"],
+            Variant::String("1".to_string())
+        );
+        assert_eq!(records[1].fields["column2"], Variant::Null);
+    }
+
+    #[test]
+    fn test_decode_latin1() {
+        let bytes = b"nome\ncaff\xe8\n";
+        assert_eq!(decode(bytes, "latin1"), "nome\ncaffè\n");
+    }
+}
@@ -1,7 +1,7 @@
-use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use crate::state::{read_config_field, Factory, Process, Record, Schema, State, Table, Variant};
 use crate::register_process;
+use indexmap::IndexMap;
 use serde_json::{Map, Value};
-use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::result::Result;
 
@@ -26,7 +26,7 @@ impl Process for InputJson {
         // Create fields and records from the parsed JSON
         let mut records: Vec<Record> = vec![];
         for record in v.as_array().unwrap() {
-            let mut fields = HashMap::new();
+            let mut fields = IndexMap::new();
             let map = record.as_object().unwrap();
             for entry in map {
                 // FIXME: unnecessary clone, i'd like to move the ownership
@@ -35,10 +35,14 @@ impl Process for InputJson {
             records.push(Record { fields });
         }
 
-        // Construct a Table
-        state.tables.push(Table {
+        // Construct a Table, json has no types so they are guessed from the values
+        let mut table = Table {
             name: self.node_name.clone(),
             records,
-        })
+            columns: vec![],
+            schema: None,
+        };
+        table.schema = Some(Schema::infer(&table));
+        state.tables.push(table)
     }
 }
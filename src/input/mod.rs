@@ -1,5 +1,11 @@
+mod csv;
 mod json;
 mod mysql;
+mod postgres;
+mod sqlite;
 
+pub use csv::*;
 pub use json::*;
 pub use mysql::*;
+pub use postgres::*;
+pub use sqlite::*;
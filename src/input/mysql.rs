@@ -1,11 +1,66 @@
-use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use crate::state::{
+    read_config_field, ColumnSchema, Factory, Process, Record, Schema, State, Table, Variant,
+    VariantKind,
+};
 use crate::register_process;
+use indexmap::IndexMap;
+use mysql::consts::{ColumnFlags, ColumnType};
 use mysql::prelude::*;
 use mysql::*;
 use serde_json::{Map, Value};
-use std::collections::HashMap;
 use std::result::Result;
 
+const BINARY_CHARSET: u16 = 63;
+
+// the text protocol returns every value as bytes, the column type tells what they are
+fn column_schema(column: &Column) -> ColumnSchema {
+    let column_type = column.column_type();
+    let kind = match column_type {
+        // unsigned bigints go beyond i64, decimals keep them exact
+        ColumnType::MYSQL_TYPE_LONGLONG if column.flags().contains(ColumnFlags::UNSIGNED_FLAG) => {
+            VariantKind::Decimal
+        }
+        ColumnType::MYSQL_TYPE_TINY
+        | ColumnType::MYSQL_TYPE_SHORT
+        | ColumnType::MYSQL_TYPE_INT24
+        | ColumnType::MYSQL_TYPE_LONG
+        | ColumnType::MYSQL_TYPE_LONGLONG
+        | ColumnType::MYSQL_TYPE_YEAR => VariantKind::Int,
+        ColumnType::MYSQL_TYPE_FLOAT | ColumnType::MYSQL_TYPE_DOUBLE => VariantKind::Float,
+        ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL => VariantKind::Decimal,
+        ColumnType::MYSQL_TYPE_DATE | ColumnType::MYSQL_TYPE_NEWDATE => VariantKind::Date,
+        ColumnType::MYSQL_TYPE_DATETIME
+        | ColumnType::MYSQL_TYPE_DATETIME2
+        | ColumnType::MYSQL_TYPE_TIMESTAMP
+        | ColumnType::MYSQL_TYPE_TIMESTAMP2 => VariantKind::DateTime,
+        ColumnType::MYSQL_TYPE_TIME | ColumnType::MYSQL_TYPE_TIME2 => VariantKind::Time,
+        ColumnType::MYSQL_TYPE_BIT => VariantKind::Bytes,
+        // blobs and binary strings share the types of texts, with the binary character set
+        ColumnType::MYSQL_TYPE_TINY_BLOB
+        | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+        | ColumnType::MYSQL_TYPE_LONG_BLOB
+        | ColumnType::MYSQL_TYPE_BLOB
+        | ColumnType::MYSQL_TYPE_VAR_STRING
+        | ColumnType::MYSQL_TYPE_STRING
+            if column.character_set() == BINARY_CHARSET =>
+        {
+            VariantKind::Bytes
+        }
+        ColumnType::MYSQL_TYPE_NULL => VariantKind::Null,
+        _ => VariantKind::String,
+    };
+    ColumnSchema {
+        name: column.name_str().to_string(),
+        kind,
+        nullable: !column.flags().contains(ColumnFlags::NOT_NULL_FLAG),
+        source_type: Some(
+            format!("{:?}", column_type)
+                .trim_start_matches("MYSQL_TYPE_")
+                .to_lowercase(),
+        ),
+    }
+}
+
 pub struct InputMysql {
     node_name: String,
     url: String,
@@ -25,17 +80,30 @@ impl Process for InputMysql {
         // println!("read_mysql_query({},{})", self.url, self.query);
         let pool = Pool::new(self.url.as_str()).unwrap();
         let result = self.query.clone().run(&pool).unwrap();
+        // the columns of the result set, known even when it has no rows
+        let schema = Schema {
+            columns: result
+                .columns()
+                .as_ref()
+                .iter()
+                .map(column_schema)
+                .collect(),
+        };
         let mut records: Vec<Record> = vec![];
         for row in result {
-            let mut fields = HashMap::new();
+            let mut fields = IndexMap::new();
             let map = row.unwrap();
-            for column in map.columns_ref() {
-                let value = &map[column.name_str().as_ref()];
-                // println!("{}: {:?}", column.name_str(), value);
-                fields.insert(
-                    column.name_str().to_string(),
-                    Variant::from_mysql_value(value.clone()),
-                );
+            for column in &schema.columns {
+                let value = &map[column.name.as_str()];
+                // println!("{}: {:?}", column.name, value);
+                let value = match (column.kind, value) {
+                    // binary values must not be read as text even when they are valid utf-8
+                    (VariantKind::Bytes, mysql::Value::Bytes(bytes)) => {
+                        Variant::Bytes(bytes.clone())
+                    }
+                    _ => column.kind.cast(&Variant::from_mysql_value(value.clone())),
+                };
+                fields.insert(column.name.clone(), value);
             }
             // println!("{:#?}", fields);
             records.push(Record { fields })
@@ -43,6 +111,36 @@ impl Process for InputMysql {
         state.tables.push(Table {
             name: self.node_name.clone(),
             records,
+            columns: schema.names(),
+            schema: Some(schema),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_unsigned_bigint_column() {
+        let column = Column::new(ColumnType::MYSQL_TYPE_LONGLONG)
+            .with_name(b"id")
+            .with_flags(ColumnFlags::UNSIGNED_FLAG | ColumnFlags::NOT_NULL_FLAG);
+        let schema = column_schema(&column);
+        assert_eq!(schema.kind, VariantKind::Decimal);
+        assert!(!schema.nullable);
+        let value =
+            Variant::from_mysql_value(mysql::Value::Bytes(b"18446744073709551615".to_vec()));
+        assert_eq!(
+            schema.kind.cast(&value),
+            Variant::Decimal(Decimal::from(u64::MAX))
+        );
+
+        let signed = Column::new(ColumnType::MYSQL_TYPE_LONGLONG).with_name(b"id");
+        let schema = column_schema(&signed);
+        assert_eq!(schema.kind, VariantKind::Int);
+        let value = Variant::from_mysql_value(mysql::Value::Bytes(b"-42".to_vec()));
+        assert_eq!(schema.kind.cast(&value), Variant::Int(-42));
+    }
+}
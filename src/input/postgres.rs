@@ -0,0 +1,180 @@
+use crate::register_process;
+use crate::state::{
+    read_config_field, ColumnSchema, Factory, Process, Record, Schema, State, Table, Variant,
+    VariantKind,
+};
+use indexmap::IndexMap;
+use postgres::types::Type;
+use postgres::{Client, Column, NoTls};
+use serde_json::{Map, Value};
+
+fn column_kind(column_type: &Type) -> Option<VariantKind> {
+    let kind = match *column_type {
+        Type::BOOL => VariantKind::Bool,
+        Type::INT2 | Type::INT4 | Type::INT8 | Type::OID => VariantKind::Int,
+        Type::FLOAT4 | Type::FLOAT8 => VariantKind::Float,
+        Type::NUMERIC => VariantKind::Decimal,
+        Type::TEXT
+        | Type::VARCHAR
+        | Type::BPCHAR
+        | Type::NAME
+        | Type::UNKNOWN
+        | Type::JSON
+        | Type::JSONB
+        | Type::UUID => VariantKind::String,
+        Type::TIMESTAMP | Type::TIMESTAMPTZ => VariantKind::DateTime,
+        Type::DATE => VariantKind::Date,
+        Type::TIME => VariantKind::Time,
+        Type::BYTEA => VariantKind::Bytes,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn column_schema(column: &Column) -> Result<ColumnSchema, String> {
+    let kind = column_kind(column.type_()).ok_or_else(|| {
+        format!(
+            "column {} has unsupported postgres type {}",
+            column.name(),
+            column.type_()
+        )
+    })?;
+    Ok(ColumnSchema {
+        name: column.name().to_string(),
+        kind,
+        // the nullability of a query column is not part of the statement description
+        nullable: true,
+        source_type: Some(column.type_().name().to_string()),
+    })
+}
+
+pub struct InputPostgres {
+    node_name: String,
+    url: String,
+    query: String,
+}
+
+impl InputPostgres {
+    fn read_table(&self) -> Result<Table, String> {
+        let mut client = Client::connect(self.url.as_str(), NoTls).map_err(|e| e.to_string())?;
+        let statement = client
+            .prepare(self.query.as_str())
+            .map_err(|e| e.to_string())?;
+        let schema = Schema {
+            columns: statement
+                .columns()
+                .iter()
+                .map(column_schema)
+                .collect::<Result<_, _>>()?,
+        };
+        let rows = client.query(&statement, &[]).map_err(|e| e.to_string())?;
+        let mut records: Vec<Record> = vec![];
+        for row in rows {
+            let mut fields = IndexMap::new();
+            for (index, column) in schema.columns.iter().enumerate() {
+                fields.insert(
+                    column.name.clone(),
+                    Variant::from_postgres_column(&row, index)?,
+                );
+            }
+            records.push(Record { fields })
+        }
+        Ok(Table {
+            name: self.node_name.clone(),
+            records,
+            columns: schema.names(),
+            schema: Some(schema),
+        })
+    }
+}
+
+impl Process for InputPostgres {
+    register_process!(input::postgres);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        InputPostgres {
+            node_name,
+            url: read_config_field(&config, "url"),
+            query: read_config_field(&config, "query"),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        match self.read_table() {
+            Ok(table) => state.tables.push(table),
+            Err(e) => {
+                eprintln!("{}: {}", self.node_name, e);
+                state.fail(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_kinds() {
+        assert_eq!(column_kind(&Type::INT2), Some(VariantKind::Int));
+        assert_eq!(column_kind(&Type::NUMERIC), Some(VariantKind::Decimal));
+        assert_eq!(column_kind(&Type::TIMESTAMPTZ), Some(VariantKind::DateTime));
+        assert_eq!(column_kind(&Type::JSONB), Some(VariantKind::String));
+        assert_eq!(column_kind(&Type::BYTEA), Some(VariantKind::Bytes));
+        assert_eq!(column_kind(&Type::POINT), None);
+    }
+
+    /// Needs a server, e.g. `POSTGRES_URL=postgres://postgres@localhost cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_read_query() {
+        let process = InputPostgres {
+            node_name: "pg".to_string(),
+            url: std::env::var("POSTGRES_URL").unwrap(),
+            query: "SELECT 1::int2 AS small, 'NaN'::numeric AS nan, \
+                    1e40::numeric AS big, 12.50::numeric AS price, \
+                    NULL::date AS day, '\\x0aff'::bytea AS raw"
+                .to_string(),
+        };
+        let table = process.read_table().unwrap();
+        let kinds: Vec<VariantKind> = table
+            .schema
+            .unwrap()
+            .columns
+            .iter()
+            .map(|c| c.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                VariantKind::Int,
+                VariantKind::Decimal,
+                VariantKind::Decimal,
+                VariantKind::Decimal,
+                VariantKind::Date,
+                VariantKind::Bytes,
+            ]
+        );
+        let record = &table.records[0];
+        assert_eq!(record.fields["small"], Variant::Int(1));
+        assert_eq!(record.fields["nan"], Variant::String("NaN".to_string()));
+        assert_eq!(
+            record.fields["big"],
+            Variant::String(format!("1{}", "0".repeat(40)))
+        );
+        assert_eq!(
+            record.fields["price"],
+            Variant::Decimal("12.50".parse().unwrap())
+        );
+        assert_eq!(record.fields["day"], Variant::Null);
+        assert_eq!(record.fields["raw"], Variant::Bytes(vec![0x0a, 0xff]));
+    }
+
+    #[test]
+    fn test_connection_error_is_reported() {
+        let process = InputPostgres {
+            node_name: "pg".to_string(),
+            url: "postgres://nobody@127.0.0.1:1/none".to_string(),
+            query: "SELECT 1".to_string(),
+        };
+        assert!(process.read_table().is_err());
+    }
+}
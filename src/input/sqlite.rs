@@ -0,0 +1,59 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use indexmap::IndexMap;
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{Map, Value};
+
+pub struct InputSqlite {
+    node_name: String,
+    path: String,
+    query: String,
+}
+
+impl InputSqlite {
+    /// Runs the query, returning the names of the columns of the result and its records.
+    pub fn read_records(&self) -> (Vec<String>, Vec<Record>) {
+        let conn =
+            Connection::open_with_flags(self.path.as_str(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .unwrap();
+        let mut statement = conn.prepare(self.query.as_str()).unwrap();
+        let columns: Vec<String> = statement
+            .column_names()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let mut rows = statement.query([]).unwrap();
+        let mut records: Vec<Record> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            let mut fields = IndexMap::new();
+            for (index, column) in columns.iter().enumerate() {
+                fields.insert(
+                    column.clone(),
+                    Variant::from_sqlite_value(row.get_ref(index).unwrap()),
+                );
+            }
+            records.push(Record { fields })
+        }
+        (columns, records)
+    }
+}
+
+impl Process for InputSqlite {
+    register_process!(input::sqlite);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        InputSqlite {
+            node_name,
+            path: read_config_field(&config, "path"),
+            query: read_config_field(&config, "query"),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let (columns, records) = self.read_records();
+        state.tables.push(Table {
+            name: self.node_name.clone(),
+            records,
+            columns,
+            schema: None,
+        })
+    }
+}
@@ -6,22 +6,37 @@
 mod input;
 mod output;
 mod state;
+mod transform;
 
 use std::process::exit;
 
 use crate::input::*;
 use crate::output::*;
 use crate::state::*;
+use crate::transform::*;
 
 use serde_json::Value;
 
 fn register_nodes(factory: &mut Factory) {
     InputMysql::register(factory);
+    InputPostgres::register(factory);
     InputJson::register(factory);
+    InputCsv::register(factory);
+    InputSqlite::register(factory);
     OutputSqlInserts::register(factory);
     OutputCompare::register(factory);
+    OutputSyncSql::register(factory);
     OutputJson::register(factory);
+    OutputCsv::register(factory);
+    OutputSqlite::register(factory);
     OutputRabbitMQ::register(factory);
+    OutputAsserts::register(factory);
+    TransformFilter::register(factory);
+    TransformJq::register(factory);
+    TransformJoin::register(factory);
+    TransformAggregate::register(factory);
+    TransformUnion::register(factory);
+    TransformSelect::register(factory);
 }
 
 fn main() -> Result<(), &'static str> {
@@ -43,6 +58,12 @@ fn main() -> Result<(), &'static str> {
         match node {
             Some(node) => {
                 node.run(&mut state);
+                if state.halted {
+                    // the failed node stays in the plan, so it is checked again on restart
+                    state.save("state.json").unwrap();
+                    eprintln!("Pipeline stopped by node {}", key);
+                    exit(state.exit_code);
+                }
             }
             None => {
                 continue;
@@ -51,5 +72,8 @@ fn main() -> Result<(), &'static str> {
         state.plan.remove(0);
         state.save("state.json").unwrap();
     }
+    if state.exit_code != 0 {
+        exit(state.exit_code);
+    }
     Ok(())
 }
@@ -1,8 +1,11 @@
-use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use crate::state::{
+    read_config_field, read_fields_list, Factory, Process, Record, State, Table, Variant,
+};
 use crate::register_process;
+use indexmap::IndexMap;
 use itertools::Itertools;
-use serde_json::{Map, Value};
-use std::collections::HashMap;
+use regex::Regex;
+use serde_json::{json, Map, Value};
 use std::io::Write;
 use std::{cmp::Ordering, fs::File, result::Result};
 
@@ -12,6 +15,38 @@ pub enum AssertState {
     RowsCountLessThan(usize),
     RowsCountEqual(usize),
     HasField(String),
+    Unique(Vec<String>),
+    NotNull(String),
+    MatchesRegex {
+        field: String,
+        pattern: String,
+    },
+    InSet {
+        field: String,
+        values: Vec<Variant>,
+    },
+    Between {
+        field: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    MaxLength {
+        field: String,
+        length: usize,
+    },
+    TypeIs {
+        field: String,
+        type_name: String,
+    },
+    References {
+        fields: Vec<String>,
+        table: String,
+        table_fields: Vec<String>,
+    },
+    SameRowsAs {
+        table: String,
+        key: Vec<String>,
+    },
     Not(Box<AssertState>),
     Or(Vec<AssertState>),
     And(Vec<AssertState>),
@@ -30,6 +65,94 @@ impl AssertState {
             ("rows-count-equal", count) => {
                 AssertState::RowsCountEqual(count.as_u64().unwrap() as usize)
             }
+            ("unique", fields) => {
+                let mut config = Map::new();
+                config.insert("fields".to_string(), fields.clone());
+                AssertState::Unique(read_fields_list(&config, "fields"))
+            }
+            ("not-null", field_name) => {
+                AssertState::NotNull(String::from(field_name.as_str().unwrap()))
+            }
+            ("matches-regex", config) => {
+                let config = config.as_object().unwrap();
+                let pattern = read_config_field(config, "pattern");
+                Regex::new(&pattern)
+                    .unwrap_or_else(|e| panic!("Invalid pattern {}: {}", pattern, e));
+                AssertState::MatchesRegex {
+                    field: read_config_field(config, "field"),
+                    pattern,
+                }
+            }
+            ("in-set", config) => {
+                let config = config.as_object().unwrap();
+                AssertState::InSet {
+                    field: read_config_field(config, "field"),
+                    values: config
+                        .get("values")
+                        .and_then(|v| v.as_array())
+                        .expect("in-set needs an array of values")
+                        .iter()
+                        .map(Variant::from_serde_value)
+                        .collect(),
+                }
+            }
+            ("between", config) => {
+                let config = config.as_object().unwrap();
+                AssertState::Between {
+                    field: read_config_field(config, "field"),
+                    min: config.get("min").map(|v| v.as_f64().unwrap()),
+                    max: config.get("max").map(|v| v.as_f64().unwrap()),
+                }
+            }
+            ("max-length", config) => {
+                let config = config.as_object().unwrap();
+                AssertState::MaxLength {
+                    field: read_config_field(config, "field"),
+                    length: config.get("length").unwrap().as_u64().unwrap() as usize,
+                }
+            }
+            ("type-is", config) => {
+                let config = config.as_object().unwrap();
+                let type_name = read_config_field(config, "type");
+                let types = [
+                    "int", "float", "number", "string", "bool", "date", "datetime", "time",
+                    "decimal", "bytes",
+                ];
+                if !types.contains(&type_name.as_str()) {
+                    panic!("Unknown type {}", type_name);
+                }
+                AssertState::TypeIs {
+                    field: read_config_field(config, "field"),
+                    type_name,
+                }
+            }
+            ("references", config) => {
+                let config = config.as_object().unwrap();
+                let fields = read_fields_list(config, "field");
+                let table_fields = match read_fields_list(config, "table_field") {
+                    table_fields if table_fields.is_empty() => fields.clone(),
+                    table_fields => table_fields,
+                };
+                if fields.is_empty() || fields.len() != table_fields.len() {
+                    panic!("references needs as many table_field as field");
+                }
+                AssertState::References {
+                    fields,
+                    table: read_config_field(config, "table"),
+                    table_fields,
+                }
+            }
+            ("same-rows-as", config) => {
+                let config = config.as_object().unwrap();
+                let key = read_fields_list(config, "key");
+                if key.is_empty() {
+                    panic!("same-rows-as needs a key");
+                }
+                AssertState::SameRowsAs {
+                    table: read_config_field(config, "table"),
+                    key,
+                }
+            }
             ("or", states) => {
                 let states = states
                     .as_array()
@@ -54,7 +177,36 @@ impl AssertState {
         }
     }
 
-    fn check(&self, table: &Table) -> (bool, Vec<String>, Vec<String>) {
+    // checks the value of a field in every record, nulls and missing fields are accepted
+    fn check_values(
+        table: &Table,
+        key: &[String],
+        field: &str,
+        description: String,
+        failure: String,
+        is_valid: impl Fn(&Variant) -> bool,
+    ) -> (bool, Vec<String>, Vec<String>) {
+        let offending: Vec<String> = table
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| match record.fields.get(field) {
+                None | Some(Variant::Null) => false,
+                Some(value) => !is_valid(value),
+            })
+            .map(|(index, record)| record_key(record, index, key))
+            .collect();
+        offending_result(offending, description, failure)
+    }
+
+    /// Checks the assert on `table`, the other tables of the state are used by the relations
+    /// between tables, `key` identifies the offending records in the error messages.
+    fn check(
+        &self,
+        table: &Table,
+        key: &[String],
+        tables: &[Table],
+    ) -> (bool, Vec<String>, Vec<String>) {
         let (result, error_messages, description) = match self {
             AssertState::Empty => (
                 table.records.is_empty(),
@@ -87,8 +239,189 @@ impl AssertState {
                     )
                 }
             }
+            AssertState::Unique(fields) => {
+                let description = format!("to have unique {}", fields.join(", "));
+                // records with a null in the unique fields are not compared, as in sql
+                let values: Vec<(usize, Vec<&Variant>)> = table
+                    .records
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, record)| {
+                        let values: Option<Vec<&Variant>> = fields
+                            .iter()
+                            .map(|f| record.fields.get(f).filter(|v| **v != Variant::Null))
+                            .collect();
+                        values.map(|v| (index, v))
+                    })
+                    .sorted_by(|(_, v1), (_, v2)| compare_values(v1, v2))
+                    .collect();
+                let offending: Vec<String> = values
+                    .chunk_by(|(_, v1), (_, v2)| compare_values(v1, v2) == Ordering::Equal)
+                    .filter(|group| group.len() > 1)
+                    .flatten()
+                    .sorted_by_key(|(index, _)| *index)
+                    .map(|(index, _)| record_key(&table.records[*index], *index, key))
+                    .collect();
+                offending_result(
+                    offending,
+                    description,
+                    format!("duplicate {}", fields.join(", ")),
+                )
+            }
+            AssertState::NotNull(field) => {
+                let offending: Vec<String> = table
+                    .records
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, record)| {
+                        matches!(record.fields.get(field), None | Some(Variant::Null))
+                    })
+                    .map(|(index, record)| record_key(record, index, key))
+                    .collect();
+                offending_result(
+                    offending,
+                    format!("to have no null {}", field),
+                    format!("null {}", field),
+                )
+            }
+            AssertState::MatchesRegex { field, pattern } => {
+                let regex = Regex::new(pattern).unwrap();
+                AssertState::check_values(
+                    table,
+                    key,
+                    field,
+                    format!("to have {} matching {}", field, pattern),
+                    format!("{} not matching {}", field, pattern),
+                    |value| regex.is_match(value.to_string().as_str()),
+                )
+            }
+            AssertState::InSet { field, values } => {
+                let set = values.iter().map(|v| v.to_string()).join(", ");
+                AssertState::check_values(
+                    table,
+                    key,
+                    field,
+                    format!("to have {} in [{}]", field, set),
+                    format!("{} not in [{}]", field, set),
+                    |value| values.iter().any(|v| v.compare(value) == Ordering::Equal),
+                )
+            }
+            AssertState::Between { field, min, max } => {
+                let range = format!(
+                    "[{}, {}]",
+                    min.map_or("-inf".to_string(), |m| m.to_string()),
+                    max.map_or("inf".to_string(), |m| m.to_string())
+                );
+                AssertState::check_values(
+                    table,
+                    key,
+                    field,
+                    format!("to have {} between {}", field, range),
+                    format!("{} not between {}", field, range),
+                    |value| match value.as_f64() {
+                        Some(v) => min.is_none_or(|m| v >= m) && max.is_none_or(|m| v <= m),
+                        None => false,
+                    },
+                )
+            }
+            AssertState::MaxLength { field, length } => AssertState::check_values(
+                table,
+                key,
+                field,
+                format!("to have {} not longer than {}", field, length),
+                format!("{} longer than {}", field, length),
+                |value| value.to_string().chars().count() <= *length,
+            ),
+            AssertState::TypeIs { field, type_name } => AssertState::check_values(
+                table,
+                key,
+                field,
+                format!("to have {} of type {}", field, type_name),
+                format!("{} not of type {}", field, type_name),
+                |value| {
+                    matches!(
+                        (type_name.as_str(), value),
+                        ("int", Variant::Int(_))
+                            | ("float", Variant::Float(_))
+                            | (
+                                "number",
+                                Variant::Int(_) | Variant::Float(_) | Variant::Decimal(_)
+                            )
+                            | ("string", Variant::String(_))
+                            | ("bool", Variant::Bool(_))
+                            | ("date", Variant::Date(_))
+                            | ("datetime", Variant::DateTime(_))
+                            | ("time", Variant::Time(_))
+                            | ("decimal", Variant::Decimal(_))
+                            | ("bytes", Variant::Bytes(_))
+                    )
+                },
+            ),
+            AssertState::References {
+                fields,
+                table: other,
+                table_fields,
+            } => {
+                let other_table = find_other_table(tables, other);
+                let description = format!(
+                    "to have {} referencing {}.{}",
+                    fields.join(", "),
+                    other,
+                    table_fields.join(", ")
+                );
+                let parents = sorted_values(other_table, table_fields);
+                // null references are not checked, as in sql
+                let orphans: Vec<String> = sorted_values(table, fields)
+                    .into_iter()
+                    .dedup_by(|v1, v2| compare_values(v1, v2) == Ordering::Equal)
+                    .filter(|v| parents.binary_search_by(|p| compare_values(p, v)).is_err())
+                    .map(|v| format_values(&v))
+                    .collect();
+                if orphans.is_empty() {
+                    (true, vec![], vec![description])
+                } else {
+                    (
+                        false,
+                        vec![format!(
+                            "{} not found in {}.{}: {}",
+                            fields.join(", "),
+                            other,
+                            table_fields.join(", "),
+                            orphans.join(", ")
+                        )],
+                        vec![description],
+                    )
+                }
+            }
+            AssertState::SameRowsAs {
+                table: other,
+                key: same_key,
+            } => {
+                let other_table = find_other_table(tables, other);
+                let description = format!("to have the same rows as {}", other);
+                let values = sorted_values(table, same_key);
+                let other_values = sorted_values(other_table, same_key);
+                let missing = |values: &[Vec<&Variant>], others: &[Vec<&Variant>]| {
+                    values
+                        .iter()
+                        .dedup_by(|v1, v2| compare_values(v1, v2) == Ordering::Equal)
+                        .filter(|v| others.binary_search_by(|o| compare_values(o, v)).is_err())
+                        .map(|v| format_values(v))
+                        .collect::<Vec<String>>()
+                };
+                let mut errors = vec![];
+                let only_here = missing(&values, &other_values);
+                if !only_here.is_empty() {
+                    errors.push(format!("missing in {}: {}", other, only_here.join(", ")));
+                }
+                let only_there = missing(&other_values, &values);
+                if !only_there.is_empty() {
+                    errors.push(format!("only in {}: {}", other, only_there.join(", ")));
+                }
+                (errors.is_empty(), errors, vec![description])
+            }
             AssertState::Not(state) => {
-                let (result, errors, description) = state.check(table);
+                let (result, errors, description) = state.check(table, key, tables);
                 let mut description2 = vec!["not".to_string()];
                 for desc in description.iter() {
                     description2.push(desc.clone());
@@ -102,8 +435,10 @@ impl AssertState {
                 }
             }
             AssertState::Or(states) => {
-                let (result, errors, description) =
-                    states.iter().map(|state| state.check(table)).fold(
+                let (result, errors, description) = states
+                    .iter()
+                    .map(|state| state.check(table, key, tables))
+                    .fold(
                         (false, vec![], vec!["or".to_string()]),
                         |(acc_result, acc_errors, acc_description), result| {
                             (
@@ -116,8 +451,10 @@ impl AssertState {
                 (result, errors, description)
             }
             AssertState::And(states) => {
-                let (result, errors, description) =
-                    states.iter().map(|state| state.check(table)).fold(
+                let (result, errors, description) = states
+                    .iter()
+                    .map(|state| state.check(table, key, tables))
+                    .fold(
                         (true, vec![], vec!["and".to_string()]),
                         |(acc_result, acc_errors, acc_description), result| {
                             (
@@ -138,14 +475,243 @@ impl AssertState {
     }
 }
 
+// identifies a record by its key fields, or by its position when no key is configured
+fn record_key(record: &Record, index: usize, key: &[String]) -> String {
+    let value = |f: &String| {
+        record
+            .fields
+            .get(f)
+            .map_or("NULL".to_string(), |v| v.to_string())
+    };
+    match key {
+        [] => format!("#{}", index + 1),
+        [field] => value(field),
+        _ => format!("({})", key.iter().map(value).join(", ")),
+    }
+}
+
+fn compare_values(v1: &[&Variant], v2: &[&Variant]) -> Ordering {
+    v1.iter()
+        .zip(v2.iter())
+        .map(|(a, b)| a.compare(b))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn find_other_table<'a>(tables: &'a [Table], name: &str) -> &'a Table {
+    tables
+        .iter()
+        .find(|t| t.name == name)
+        .unwrap_or_else(|| panic!("table {} not found", name))
+}
+
+// the values of the fields in every record, sorted; records with a null in the fields are skipped
+fn sorted_values<'a>(table: &'a Table, fields: &[String]) -> Vec<Vec<&'a Variant>> {
+    table
+        .records
+        .iter()
+        .filter_map(|record| {
+            fields
+                .iter()
+                .map(|f| record.fields.get(f).filter(|v| **v != Variant::Null))
+                .collect::<Option<Vec<&Variant>>>()
+        })
+        .sorted_by(|v1, v2| compare_values(v1, v2))
+        .collect()
+}
+
+fn format_values(values: &[&Variant]) -> String {
+    match values {
+        [value] => Variant::to_string(value),
+        _ => format!(
+            "({})",
+            values.iter().map(|v| Variant::to_string(v)).join(", ")
+        ),
+    }
+}
+
+fn offending_result(
+    offending: Vec<String>,
+    description: String,
+    failure: String,
+) -> (bool, Vec<String>, Vec<String>) {
+    if offending.is_empty() {
+        (true, vec![], vec![description])
+    } else {
+        (
+            false,
+            vec![format!("{} in records: {}", failure, offending.join(", "))],
+            vec![description],
+        )
+    }
+}
+
 pub struct Assert {
     table: String,
+    /// fields used to identify the offending records in the results
+    key: Vec<String>,
     state: AssertState,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OnFailure {
+    /// report the failures and go on with the plan
+    Warn,
+    /// stop the plan before the next node, with a non-zero exit code
+    Stop,
+    /// go on with the plan, but exit with a non-zero code at the end
+    ExitCode,
+}
+
+impl OnFailure {
+    fn parse(value: &str) -> Self {
+        match value {
+            "warn" => OnFailure::Warn,
+            "stop" => OnFailure::Stop,
+            "exit-code" => OnFailure::ExitCode,
+            _ => panic!("Unknown on_failure policy {}", value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResultsFormat {
+    Text,
+    Junit,
+    Json,
+}
+
+impl ResultsFormat {
+    fn parse(value: &str) -> Self {
+        match value {
+            "text" => ResultsFormat::Text,
+            "junit" => ResultsFormat::Junit,
+            "json" => ResultsFormat::Json,
+            _ => panic!("Unknown results format {}", value),
+        }
+    }
+    fn extension(&self) -> &'static str {
+        match self {
+            ResultsFormat::Text => "txt",
+            ResultsFormat::Junit => "xml",
+            ResultsFormat::Json => "json",
+        }
+    }
+}
+
+pub struct AssertResult {
+    pub table: String,
+    pub passed: bool,
+    pub errors: Vec<String>,
+    pub description: Vec<String>,
+}
+
+impl AssertResult {
+    fn name(&self) -> String {
+        format!("table {} {}", self.table, self.description.join(" "))
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 pub struct OutputAsserts {
     node_name: String,
     asserts: Vec<Assert>,
+    on_failure: OnFailure,
+    format: ResultsFormat,
+    path: String,
+}
+
+impl OutputAsserts {
+    pub fn check_all(&self, tables: &[Table]) -> Vec<AssertResult> {
+        self.asserts
+            .iter()
+            .map(|assert| {
+                let table = find_other_table(tables, &assert.table);
+                let (passed, errors, description) = assert.state.check(table, &assert.key, tables);
+                AssertResult {
+                    table: assert.table.clone(),
+                    passed,
+                    errors,
+                    description,
+                }
+            })
+            .collect()
+    }
+
+    pub fn format_text(&self, results: &[AssertResult]) -> Vec<String> {
+        results
+            .iter()
+            .filter(|r| !r.passed)
+            .map(|r| format!("table {} failed assert: {:?}", r.table, r.errors))
+            .collect()
+    }
+
+    /// One testsuite named after the node, with a testcase for every assert.
+    pub fn format_junit(&self, results: &[AssertResult]) -> Vec<String> {
+        let failures = results.iter().filter(|r| !r.passed).count();
+        let mut lines = vec![
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string(),
+            format!(
+                "<testsuites tests=\"{}\" failures=\"{}\">",
+                results.len(),
+                failures
+            ),
+            format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+                escape_xml(&self.node_name),
+                results.len(),
+                failures
+            ),
+        ];
+        for result in results {
+            let testcase = format!(
+                "    <testcase classname=\"{}.{}\" name=\"{}\"",
+                escape_xml(&self.node_name),
+                escape_xml(&result.table),
+                escape_xml(&result.name())
+            );
+            if result.passed {
+                lines.push(format!("{}/>", testcase));
+            } else {
+                lines.push(format!("{}>", testcase));
+                lines.push(format!(
+                    "      <failure message=\"{}\">{}</failure>",
+                    escape_xml(&result.errors.join("; ")),
+                    escape_xml(&result.errors.join("\n"))
+                ));
+                lines.push("    </testcase>".to_string());
+            }
+        }
+        lines.push("  </testsuite>".to_string());
+        lines.push("</testsuites>".to_string());
+        lines
+    }
+
+    pub fn format_json(&self, results: &[AssertResult]) -> Value {
+        let failed = results.iter().filter(|r| !r.passed).count();
+        json!({
+            "node": self.node_name,
+            "total": results.len(),
+            "passed": results.len() - failed,
+            "failed": failed,
+            "results": results
+                .iter()
+                .map(|r| json!({
+                    "table": r.table,
+                    "description": r.description.join(" "),
+                    "passed": r.passed,
+                    "errors": r.errors,
+                }))
+                .collect::<Vec<Value>>(),
+        })
+    }
 }
 
 impl Process for OutputAsserts {
@@ -162,36 +728,61 @@ impl Process for OutputAsserts {
                 let state = AssertState::parse(assert.get("state").unwrap());
                 Assert {
                     table: String::from(table),
+                    key: read_fields_list(assert.as_object().unwrap(), "key"),
                     state,
                 }
             })
             .collect();
-        Self { node_name, asserts }
+        let on_failure = OnFailure::parse(
+            config
+                .get("on_failure")
+                .and_then(|v| v.as_str())
+                .unwrap_or("warn"),
+        );
+        let format = ResultsFormat::parse(
+            config
+                .get("format")
+                .and_then(|v| v.as_str())
+                .unwrap_or("text"),
+        );
+        let path = config
+            .get("path")
+            .and_then(|v| v.as_str())
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| format!("{}_results.{}", node_name, format.extension()));
+        Self {
+            node_name,
+            asserts,
+            on_failure,
+            format,
+            path,
+        }
     }
     fn run(&self, state: &mut State) {
-        /* we check each assert, and store all the errors in a vector, and at the end we write them in a "<node_name>_results.txt" file
+        /* we check each assert, and write the results in a "<node_name>_results.<format extension>" file,
+        the text format lists only the errors
          */
 
-        let mut errors: Vec<String> = vec![];
-
-        for assert in &self.asserts {
-            let table = state.find_table(assert.table.as_str()).unwrap();
-            let (result, ass_errors, _) = assert.state.check(table);
-            if !result {
-                errors.push(format!(
-                    "table {} failed assert: {:?}",
-                    assert.table, ass_errors
-                ));
+        let results = self.check_all(&state.tables);
+        let errors = self.format_text(&results);
+        let output = match self.format {
+            ResultsFormat::Text => errors.clone(),
+            ResultsFormat::Junit => self.format_junit(&results),
+            ResultsFormat::Json => {
+                vec![serde_json::to_string_pretty(&self.format_json(&results)).unwrap()]
+            }
+        };
+        state.write_file(self.path.as_str(), &output);
+        if !errors.is_empty() {
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            match self.on_failure {
+                OnFailure::Warn => {}
+                OnFailure::Stop => state.fail(true),
+                OnFailure::ExitCode => state.fail(false),
             }
         }
-
-        let file_name = format!("{}_results.txt", self.node_name);
-        // let mut file = File::create(file_name).unwrap();
-        // for error in errors {
-        //     file.write_all(error.as_bytes()).unwrap();
-        //     file.write_all("\n".as_bytes()).unwrap();
-        // }
-        state.write_file(file_name.as_str(), &errors);
     }
 }
 
@@ -382,6 +973,8 @@ mod tests {
         state.tables.push(Table {
             name: "table1".to_string(),
             records: vec![],
+            columns: vec![],
+            schema: None,
         });
         let process = OutputAsserts::from_config(
             "test".to_string(),
@@ -430,13 +1023,15 @@ mod tests {
         );
 
         let mut rec1 = Record {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
         };
         rec1.fields
             .insert("name".to_string(), Variant::String("test".to_string()));
         state.tables.push(Table {
             name: "table1".to_string(),
             records: vec![rec1],
+            columns: vec![],
+            schema: None,
         });
         let process = OutputAsserts::from_config(
             "test".to_string(),
@@ -488,13 +1083,15 @@ mod tests {
         );
 
         let mut rec1 = Record {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
         };
         rec1.fields
             .insert("name".to_string(), Variant::String("test".to_string()));
         state.tables.push(Table {
             name: "table1".to_string(),
             records: vec![rec1],
+            columns: vec![],
+            schema: None,
         });
         let process = OutputAsserts::from_config(
             "test".to_string(),
@@ -548,6 +1145,8 @@ mod tests {
         state.tables.push(Table {
             name: "table1".to_string(),
             records: vec![],
+            columns: vec![],
+            schema: None,
         });
         let process = OutputAsserts::from_config(
             "test".to_string(),
@@ -598,18 +1197,20 @@ mod tests {
         );
 
         let mut rec1 = Record {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
         };
         rec1.fields
             .insert("name".to_string(), Variant::String("test".to_string()));
         let mut rec2 = Record {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
         };
         rec2.fields
             .insert("name".to_string(), Variant::String("test2".to_string()));
         state.tables.push(Table {
             name: "table1".to_string(),
             records: vec![rec1, rec2],
+            columns: vec![],
+            schema: None,
         });
         let process = OutputAsserts::from_config(
             "test".to_string(),
@@ -626,4 +1227,343 @@ mod tests {
             1
         );
     }
+
+    fn run_failing_assert(on_failure: Option<&str>) -> State {
+        let config = Config {
+            state_file: "state.json".to_string(),
+            template_file: "template.json".to_string(),
+        };
+        let mut node = serde_json::json!({
+            "driver": "output::asserts",
+            "asserts": [{"table": "table1", "state": {"not": {"empty": ""}}}]
+        });
+        if let Some(on_failure) = on_failure {
+            node["on_failure"] = Value::String(on_failure.to_string());
+        }
+        let pipeline = serde_json::json!({ "test": node });
+        let mut state = State::make(
+            &config,
+            &pipeline,
+            Some(Box::new(MemoryWriter::new())),
+            Some(Box::new(MemoryReader::new())),
+        );
+        state.tables.push(Table {
+            name: "table1".to_string(),
+            records: vec![],
+            columns: vec![],
+            schema: None,
+        });
+        let process = OutputAsserts::from_config(
+            "test".to_string(),
+            pipeline.get("test").unwrap().as_object().unwrap().clone(),
+        );
+        process.run(&mut state);
+        assert_eq!(
+            state
+                .results_writer
+                .test_peek("test_results.txt")
+                .unwrap()
+                .len(),
+            1
+        );
+        state
+    }
+
+    #[test]
+    fn test_on_failure_policies() {
+        let state = run_failing_assert(None);
+        assert_eq!((state.exit_code, state.halted), (0, false));
+        let state = run_failing_assert(Some("warn"));
+        assert_eq!((state.exit_code, state.halted), (0, false));
+        let state = run_failing_assert(Some("stop"));
+        assert_eq!((state.exit_code, state.halted), (1, true));
+        let state = run_failing_assert(Some("exit-code"));
+        assert_eq!((state.exit_code, state.halted), (1, false));
+    }
+
+    fn make_people() -> Table {
+        let rows = [
+            (
+                1,
+                Variant::String("alice@example.com".to_string()),
+                Variant::Int(30),
+            ),
+            (2, Variant::String("bob".to_string()), Variant::Float(150.5)),
+            (
+                3,
+                Variant::String("alice@example.com".to_string()),
+                Variant::Null,
+            ),
+            (4, Variant::Null, Variant::String("old".to_string())),
+        ];
+        Table {
+            name: "people".to_string(),
+            records: rows
+                .into_iter()
+                .map(|(id, email, age)| {
+                    let mut fields = IndexMap::new();
+                    fields.insert("id".to_string(), Variant::Int(id));
+                    fields.insert("email".to_string(), email);
+                    fields.insert("age".to_string(), age);
+                    Record { fields }
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn check_people(state: &str, key: &[&str]) -> (bool, Vec<String>) {
+        let state = AssertState::parse(&serde_json::from_str(state).unwrap());
+        let key: Vec<String> = key.iter().map(|k| k.to_string()).collect();
+        let (result, errors, _) = state.check(&make_people(), &key, &[]);
+        (result, errors)
+    }
+
+    #[test]
+    fn test_column_asserts_report_offending_keys() {
+        assert_eq!(
+            check_people(r#"{"unique":"email"}"#, &["id"]),
+            (false, vec!["duplicate email in records: 1, 3".to_string()])
+        );
+        assert_eq!(
+            check_people(r#"{"unique":["email","age"]}"#, &["id"]),
+            (true, vec![])
+        );
+        assert_eq!(
+            check_people(r#"{"not-null":"age"}"#, &["id"]),
+            (false, vec!["null age in records: 3".to_string()])
+        );
+        assert_eq!(
+            check_people(
+                r#"{"matches-regex":{"field":"email","pattern":"^[^@]+@[^@]+$"}}"#,
+                &["id", "email"]
+            ),
+            (
+                false,
+                vec!["email not matching ^[^@]+@[^@]+$ in records: (2, bob)".to_string()]
+            )
+        );
+        assert_eq!(
+            check_people(
+                r#"{"in-set":{"field":"email","values":["bob","alice@example.com"]}}"#,
+                &[]
+            ),
+            (true, vec![])
+        );
+        assert_eq!(
+            check_people(r#"{"between":{"field":"age","min":0,"max":120}}"#, &[]),
+            (
+                false,
+                vec!["age not between [0, 120] in records: #2, #4".to_string()]
+            )
+        );
+        assert_eq!(
+            check_people(r#"{"max-length":{"field":"email","length":3}}"#, &["id"]),
+            (
+                false,
+                vec!["email longer than 3 in records: 1, 3".to_string()]
+            )
+        );
+        assert_eq!(
+            check_people(r#"{"type-is":{"field":"age","type":"number"}}"#, &["id"]),
+            (
+                false,
+                vec!["age not of type number in records: 4".to_string()]
+            )
+        );
+        assert_eq!(
+            check_people(
+                r#"{"not":{"type-is":{"field":"id","type":"string"}}}"#,
+                &["id"]
+            ),
+            (true, vec![])
+        );
+        let mut people = make_people();
+        people.records[1].fields.insert(
+            "age".to_string(),
+            Variant::Decimal(rust_decimal::Decimal::new(1505, 1)),
+        );
+        people.records[3].fields.insert(
+            "age".to_string(),
+            Variant::Date(chrono::NaiveDate::from_ymd_opt(1990, 1, 1).unwrap()),
+        );
+        let check = |state: &str| {
+            let state = AssertState::parse(&serde_json::from_str(state).unwrap());
+            state.check(&people, &["id".to_string()], &[]).1
+        };
+        assert_eq!(
+            check(r#"{"type-is":{"field":"age","type":"number"}}"#),
+            vec!["age not of type number in records: 4".to_string()]
+        );
+        assert_eq!(
+            check(r#"{"type-is":{"field":"age","type":"decimal"}}"#),
+            vec!["age not of type decimal in records: 1, 4".to_string()]
+        );
+        assert_eq!(
+            check(r#"{"type-is":{"field":"age","type":"date"}}"#),
+            vec!["age not of type date in records: 1, 2".to_string()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid pattern [a-")]
+    fn test_invalid_regex() {
+        check_people(
+            r#"{"matches-regex":{"field":"email","pattern":"[a-"}}"#,
+            &[],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown type integer")]
+    fn test_unknown_type_name() {
+        check_people(r#"{"type-is":{"field":"id","type":"integer"}}"#, &[]);
+    }
+
+    #[test]
+    fn test_between_non_numeric_values() {
+        // strings are out of any range, even when they look like numbers; nulls are skipped
+        assert_eq!(
+            check_people(r#"{"between":{"field":"email","min":0}}"#, &["id"]),
+            (
+                false,
+                vec!["email not between [0, inf] in records: 1, 2, 3".to_string()]
+            )
+        );
+        let mut people = make_people();
+        people.records[0]
+            .fields
+            .insert("age".to_string(), Variant::String("30".to_string()));
+        let state = AssertState::parse(
+            &serde_json::from_str(r#"{"between":{"field":"age","max":200}}"#).unwrap(),
+        );
+        assert_eq!(
+            state.check(&people, &["id".to_string()], &[]).1,
+            vec!["age not between [-inf, 200] in records: 1, 4".to_string()]
+        );
+    }
+
+    fn make_ids(name: &str, ids: &[Option<i64>]) -> Table {
+        Table {
+            name: name.to_string(),
+            records: ids
+                .iter()
+                .map(|id| {
+                    let mut fields = IndexMap::new();
+                    fields.insert(
+                        "customer_id".to_string(),
+                        id.map_or(Variant::Null, Variant::Int),
+                    );
+                    Record { fields }
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_cross_table_asserts_list_orphans() {
+        let orders = make_ids("orders", &[Some(1), Some(5), None, Some(5), Some(7)]);
+        let mut customers = make_ids("customers", &[Some(1), Some(2), Some(7)]);
+        for record in customers.records.iter_mut() {
+            let id = record.fields.shift_remove("customer_id").unwrap();
+            record.fields.insert("id".to_string(), id);
+        }
+        let tables = vec![customers];
+        let state = AssertState::parse(
+            &serde_json::from_str(
+                r#"{"references":{"field":"customer_id","table":"customers","table_field":"id"}}"#,
+            )
+            .unwrap(),
+        );
+        let (result, errors, _) = state.check(&orders, &[], &tables);
+        assert!(!result);
+        assert_eq!(errors, vec!["customer_id not found in customers.id: 5"]);
+
+        let copy = make_ids("copy", &[Some(1), Some(2), Some(3)]);
+        let tables = vec![copy];
+        let state = AssertState::parse(
+            &serde_json::from_str(r#"{"same-rows-as":{"table":"copy","key":"customer_id"}}"#)
+                .unwrap(),
+        );
+        let (result, errors, _) = state.check(&orders, &[], &tables);
+        assert!(!result);
+        assert_eq!(errors, vec!["missing in copy: 5, 7", "only in copy: 2, 3"]);
+        let (result, _, _) = state.check(
+            &make_ids("same", &[Some(3), Some(2), Some(1)]),
+            &[],
+            &tables,
+        );
+        assert!(result);
+        let state = AssertState::parse(
+            &serde_json::from_str(r#"{"same-rows-as":{"table":"people","key":["email","id"]}}"#)
+                .unwrap(),
+        );
+        let (_, errors, _) = state.check(
+            &make_people(),
+            &[],
+            &[Table {
+                name: "people".to_string(),
+                records: vec![],
+                columns: vec![],
+                schema: None,
+            }],
+        );
+        assert_eq!(
+            errors,
+            vec!["missing in people: (alice@example.com, 1), (alice@example.com, 3), (bob, 2)"]
+        );
+    }
+
+    #[test]
+    fn test_junit_and_json_results() {
+        let config = serde_json::json!({
+            "driver": "output::asserts",
+            "format": "junit",
+            "asserts": [
+                {"table": "people", "state": {"not-null": "email"}},
+                {"table": "people", "key": "id", "state": {"unique": "email"}}
+            ]
+        });
+        let process =
+            OutputAsserts::from_config("checks".to_string(), config.as_object().unwrap().clone());
+        assert_eq!(process.path, "checks_results.xml");
+        let results = process.check_all(&[make_people()]);
+
+        assert_eq!(
+            process.format_junit(&results),
+            vec![
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<testsuites tests="2" failures="2">"#,
+                r#"  <testsuite name="checks" tests="2" failures="2">"#,
+                r#"    <testcase classname="checks.people" name="table people to have no null email">"#,
+                r#"      <failure message="null email in records: #4">null email in records: #4</failure>"#,
+                r#"    </testcase>"#,
+                r#"    <testcase classname="checks.people" name="table people to have unique email">"#,
+                r#"      <failure message="duplicate email in records: 1, 3">duplicate email in records: 1, 3</failure>"#,
+                r#"    </testcase>"#,
+                r#"  </testsuite>"#,
+                r#"</testsuites>"#,
+            ]
+        );
+
+        let summary = process.format_json(&results[1..]);
+        assert_eq!(
+            summary,
+            serde_json::json!({
+                "node": "checks",
+                "total": 1,
+                "passed": 0,
+                "failed": 1,
+                "results": [{
+                    "table": "people",
+                    "description": "to have unique email",
+                    "passed": false,
+                    "errors": ["duplicate email in records: 1, 3"]
+                }]
+            })
+        );
+    }
 }
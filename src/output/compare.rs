@@ -1,133 +1,1210 @@
-use crate::state::{read_config_field, Factory, Process, State, Table};
 use crate::register_process;
+use crate::state::{
+    read_config_field, read_fields_list, Factory, Process, Record, State, Table, Variant,
+};
+use indexmap::IndexMap;
 use itertools::Itertools;
-use serde_json::{Map, Value};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
 use std::{cmp::Ordering, fs::File, result::Result};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coercion {
+    Number,
+    String,
+}
+
+/// How two values of the same column are considered equal.
+#[derive(Debug, Clone, Default)]
+pub struct ComparisonRules {
+    pub tolerance: f64,
+    pub case_insensitive: bool,
+    pub ignore_whitespace: bool,
+    pub coerce: Option<Coercion>,
+}
+
+impl ComparisonRules {
+    fn from_config(config: &Map<String, Value>, defaults: &ComparisonRules) -> Self {
+        let flag = |name: &str, default: bool| {
+            config
+                .get(name)
+                .map(|v| v.as_bool().unwrap())
+                .unwrap_or(default)
+        };
+        ComparisonRules {
+            tolerance: config
+                .get("tolerance")
+                .map(|v| v.as_f64().unwrap())
+                .unwrap_or(defaults.tolerance),
+            case_insensitive: flag("case_insensitive", defaults.case_insensitive),
+            ignore_whitespace: flag("ignore_whitespace", defaults.ignore_whitespace),
+            coerce: match config.get("coerce").and_then(|c| c.as_str()) {
+                Some("number") => Some(Coercion::Number),
+                Some("string") => Some(Coercion::String),
+                Some(other) => panic!("Unknown coercion {}", other),
+                None => defaults.coerce,
+            },
+        }
+    }
+
+    pub fn normalize(&self, value: &Variant) -> Variant {
+        let value = match (self.coerce, value) {
+            (Some(Coercion::Number), Variant::String(s)) => {
+                let trimmed = s.trim();
+                if let Ok(i) = trimmed.parse::<i64>() {
+                    Variant::Int(i)
+                } else if let Ok(f) = trimmed.parse::<f64>() {
+                    Variant::Float(f)
+                } else {
+                    value.clone()
+                }
+            }
+            (Some(Coercion::String), Variant::Int(_) | Variant::Float(_)) => {
+                Variant::String(value.to_string())
+            }
+            _ => value.clone(),
+        };
+        match value {
+            Variant::String(s) => {
+                let s = if self.ignore_whitespace {
+                    s.split_whitespace().join(" ")
+                } else {
+                    s
+                };
+                let s = if self.case_insensitive {
+                    s.to_lowercase()
+                } else {
+                    s
+                };
+                Variant::String(s)
+            }
+            _ => value,
+        }
+    }
+
+    pub fn equal(&self, v1: &Variant, v2: &Variant) -> bool {
+        let v1 = self.normalize(v1);
+        let v2 = self.normalize(v2);
+        let exact = |v: &Variant| match v {
+            Variant::Int(i) => Some(Decimal::from(*i)),
+            Variant::Decimal(d) => Some(*d),
+            _ => None,
+        };
+        if self.tolerance == 0.0 {
+            return v1.compare(&v2) == Ordering::Equal;
+        }
+        // ints and decimals are compared as decimals, going through f64 would lose digits
+        match (exact(&v1), exact(&v2), v1.as_f64(), v2.as_f64()) {
+            (Some(d1), Some(d2), _, _) => d1.checked_sub(d2).is_some_and(|d| {
+                d.abs() <= Decimal::from_f64(self.tolerance).unwrap_or(Decimal::MAX)
+            }),
+            (_, _, Some(f1), Some(f2)) => f1 == f2 || (f1 - f2).abs() <= self.tolerance,
+            _ => v1.compare(&v2) == Ordering::Equal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Fields are always named as in the left table, `field_mapping` gives their name in the right one.
+pub struct CompareOptions {
+    pub identity_fields: Vec<String>,
+    pub rules: ComparisonRules,
+    pub column_rules: HashMap<String, ComparisonRules>,
+    pub ignore_fields: Vec<String>,
+    pub field_mapping: HashMap<String, String>,
+}
+
+impl CompareOptions {
+    /// `identity_field` can be a single field name or an array of them for composite keys,
+    /// the comparison rules can be set for all columns and overridden in `column_rules`.
+    pub fn from_config(config: &Map<String, Value>) -> Self {
+        let identity_fields = read_fields_list(config, "identity_field");
+        if identity_fields.is_empty() {
+            panic!("identity_field is required");
+        }
+        let rules = ComparisonRules::from_config(config, &ComparisonRules::default());
+        let column_rules = config
+            .get("column_rules")
+            .unwrap_or(&Value::Null)
+            .as_object()
+            .unwrap_or(&Map::new())
+            .iter()
+            .map(|(column, c)| {
+                (
+                    column.clone(),
+                    ComparisonRules::from_config(c.as_object().unwrap(), &rules),
+                )
+            })
+            .collect();
+        let field_mapping = config
+            .get("field_mapping")
+            .unwrap_or(&Value::Null)
+            .as_object()
+            .unwrap_or(&Map::new())
+            .iter()
+            .map(|(left, right)| (left.clone(), right.as_str().unwrap().to_string()))
+            .collect();
+        CompareOptions {
+            identity_fields,
+            rules,
+            column_rules,
+            ignore_fields: read_fields_list(config, "ignore_fields"),
+            field_mapping,
+        }
+    }
+
+    pub fn right_name<'a>(&'a self, left_name: &'a str) -> &'a str {
+        self.field_mapping
+            .get(left_name)
+            .map(|n| n.as_str())
+            .unwrap_or(left_name)
+    }
+
+    pub fn left_name<'a>(&'a self, right_name: &'a str) -> &'a str {
+        self.field_mapping
+            .iter()
+            .find(|(_, right)| *right == right_name)
+            .map(|(left, _)| left.as_str())
+            .unwrap_or(right_name)
+    }
+
+    pub fn field_name<'a>(&'a self, left_name: &'a str, side: Side) -> &'a str {
+        match side {
+            Side::Left => left_name,
+            Side::Right => self.right_name(left_name),
+        }
+    }
+
+    pub fn is_ignored(&self, left_name: &str) -> bool {
+        self.ignore_fields.iter().any(|f| f == left_name)
+    }
+
+    pub fn has_key(&self, record: &Record, side: Side) -> bool {
+        self.identity_fields
+            .iter()
+            .all(|f| record.fields.contains_key(self.field_name(f, side)))
+    }
+
+    /// The identity fields of a record, named as in the left table.
+    pub fn key_values<'a>(&'a self, record: &'a Record, side: Side) -> Vec<(&'a str, &'a Variant)> {
+        self.identity_fields
+            .iter()
+            .map(|f| (f.as_str(), &record.fields[self.field_name(f, side)]))
+            .collect()
+    }
+
+    /// The identity of a record, formatted as a single value or as a tuple for composite keys.
+    pub fn format_key(&self, record: &Record, side: Side) -> String {
+        let values = self
+            .identity_fields
+            .iter()
+            .map(|f| format!("{}", record.fields[self.field_name(f, side)]))
+            .join(", ");
+        if self.identity_fields.len() == 1 {
+            values
+        } else {
+            format!("({})", values)
+        }
+    }
+
+    pub fn rules_for(&self, column: &str) -> &ComparisonRules {
+        self.column_rules.get(column).unwrap_or(&self.rules)
+    }
+
+    fn key(&self, record: &Record, side: Side) -> Vec<Variant> {
+        self.identity_fields
+            .iter()
+            .map(|f| {
+                self.rules_for(f)
+                    .normalize(&record.fields[self.field_name(f, side)])
+            })
+            .collect()
+    }
+}
+
+fn compare_keys(k1: &[Variant], k2: &[Variant]) -> Ordering {
+    k1.iter()
+        .zip(k2)
+        .map(|(v1, v2)| v1.compare(v2))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FieldDifference<'a> {
+    Mismatch {
+        left: &'a str,
+        right: &'a str,
+    },
+    /// a field of the right record, named as in the right table
+    MissingInLeft(&'a str),
+    /// a field of the left record
+    MissingInRight(&'a str),
+}
+
+/// One difference between two tables, in identity field order.
+#[derive(Debug)]
+pub enum Difference<'a> {
+    OnlyInLeft(&'a Record),
+    OnlyInRight(&'a Record),
+    /// records lacking one of the identity fields cannot be matched at all
+    MissingKeyInLeft(&'a Record),
+    MissingKeyInRight(&'a Record),
+    Changed {
+        left: &'a Record,
+        right: &'a Record,
+        fields: Vec<FieldDifference<'a>>,
+    },
+}
+
+fn compare_records<'a>(
+    r1: &'a Record,
+    r2: &'a Record,
+    options: &CompareOptions,
+) -> Vec<FieldDifference<'a>> {
+    let mut fields = vec![];
+    // find fields that are different
+    for (k, v) in &r1.fields {
+        if options.is_ignored(k) {
+            continue;
+        }
+        match r2.fields.get_key_value(options.right_name(k)) {
+            Some((k2, v2)) if !options.rules_for(k).equal(v, v2) => {
+                fields.push(FieldDifference::Mismatch {
+                    left: k.as_str(),
+                    right: k2.as_str(),
+                })
+            }
+            Some(_) => {}
+            None => fields.push(FieldDifference::MissingInRight(k.as_str())),
+        }
+    }
+    // find fields that are in r2 but not in r1
+    for k2 in r2.fields.keys() {
+        let k = options.left_name(k2);
+        if !options.is_ignored(k) && !r1.fields.contains_key(k) {
+            fields.push(FieldDifference::MissingInLeft(k2.as_str()));
+        }
+    }
+    fields
+}
+
+pub fn diff_tables<'a>(
+    t1: &'a Table,
+    t2: &'a Table,
+    options: &CompareOptions,
+) -> Vec<Difference<'a>> {
+    let (keyed1, unkeyed1): (Vec<&Record>, Vec<&Record>) = t1
+        .records
+        .iter()
+        .partition(|r| options.has_key(r, Side::Left));
+    let (keyed2, unkeyed2): (Vec<&Record>, Vec<&Record>) = t2
+        .records
+        .iter()
+        .partition(|r| options.has_key(r, Side::Right));
+    let mut differences: Vec<Difference> = unkeyed1
+        .into_iter()
+        .map(Difference::MissingKeyInLeft)
+        .chain(unkeyed2.into_iter().map(Difference::MissingKeyInRight))
+        .collect();
+    let mut sorted1 = keyed1
+        .into_iter()
+        .map(|r| (options.key(r, Side::Left), r))
+        .sorted_by(|(k1, _), (k2, _)| compare_keys(k1, k2))
+        .map(|(_, r)| r);
+    let mut sorted2 = keyed2
+        .into_iter()
+        .map(|r| (options.key(r, Side::Right), r))
+        .sorted_by(|(k1, _), (k2, _)| compare_keys(k1, k2))
+        .map(|(_, r)| r);
+    let mut it1 = sorted1.next();
+    let mut it2 = sorted2.next();
+    loop {
+        match (it1, it2) {
+            (Some(r1), Some(r2)) => {
+                match compare_keys(&options.key(r1, Side::Left), &options.key(r2, Side::Right)) {
+                    Ordering::Less => {
+                        differences.push(Difference::OnlyInLeft(r1));
+                        it1 = sorted1.next();
+                    }
+                    Ordering::Greater => {
+                        differences.push(Difference::OnlyInRight(r2));
+                        it2 = sorted2.next();
+                    }
+                    Ordering::Equal => {
+                        let fields = compare_records(r1, r2, options);
+                        if !fields.is_empty() {
+                            differences.push(Difference::Changed {
+                                left: r1,
+                                right: r2,
+                                fields,
+                            });
+                        }
+                        it1 = sorted1.next();
+                        it2 = sorted2.next();
+                    }
+                }
+            }
+            (Some(r1), None) => {
+                differences.push(Difference::OnlyInLeft(r1));
+                it1 = sorted1.next();
+            }
+            (None, Some(r2)) => {
+                differences.push(Difference::OnlyInRight(r2));
+                it2 = sorted2.next();
+            }
+            (None, None) => {
+                break; //exit loop
+            }
+        }
+    }
+    differences
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Html,
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+th { background: #eee; }
+td.changed { background: #fdd; font-weight: bold; }
+td.missing { background: #eee; color: #888; }
+em.null { color: #888; }";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_value(value: Option<&Variant>) -> String {
+    match value {
+        None => "".to_string(),
+        Some(Variant::Null) => "<em class=\"null\">NULL</em>".to_string(),
+        Some(value) => escape_html(value.to_string().as_str()),
+    }
+}
+
+// a table with the union of the fields of the records as columns
+fn html_records(lines: &mut Vec<String>, records: &[&Record]) {
+    let columns: Vec<&String> = records
+        .iter()
+        .flat_map(|r| r.fields.keys())
+        .unique()
+        .collect();
+    lines.push("<table>".to_string());
+    lines.push(format!(
+        "<tr>{}</tr>",
+        columns
+            .iter()
+            .map(|c| format!("<th>{}</th>", escape_html(c)))
+            .join("")
+    ));
+    for record in records {
+        lines.push(format!(
+            "<tr>{}</tr>",
+            columns
+                .iter()
+                .map(|c| format!("<td>{}</td>", html_value(record.fields.get(*c))))
+                .join("")
+        ));
+    }
+    lines.push("</table>".to_string());
+}
+
+impl ReportFormat {
+    fn parse(value: &str) -> Self {
+        match value {
+            "text" => ReportFormat::Text,
+            "json" => ReportFormat::Json,
+            "html" => ReportFormat::Html,
+            _ => panic!("Unknown report format {}", value),
+        }
+    }
+}
+
 pub struct OutputCompare {
     node_name: String,
     input1: String,
     input2: String,
     path: String,
-    identity_field: String,
+    options: CompareOptions,
+    format: ReportFormat,
+    save_table: bool,
 }
 
-impl Process for OutputCompare {
-    register_process!(output::compare-table);
-    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
-        OutputCompare {
-            node_name,
-            input1: read_config_field(&config, "input1"),
-            input2: read_config_field(&config, "input2"),
-            path: read_config_field(&config, "path"),
-            identity_field: read_config_field(&config, "identity_field"),
-        }
+impl OutputCompare {
+    fn key_object(&self, record: &Record, side: Side) -> Value {
+        Value::Object(
+            self.options
+                .key_values(record, side)
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_serde_value()))
+                .collect(),
+        )
     }
-    fn run(&self, state: &mut State) {
-        let t1 = state.find_table(self.input1.as_str()).unwrap();
-        let t2 = state.find_table(self.input2.as_str()).unwrap();
 
-        let mut differences: Vec<String> = vec![];
-        let mut sorted1 = t1.records.iter().sorted_by(|a, b| {
-            Ord::cmp(
-                a.fields[self.identity_field.as_str()].to_string().as_str(),
-                b.fields[self.identity_field.as_str()].to_string().as_str(),
-            )
-        });
-        let mut sorted2 = t2.records.iter().sorted_by(|a, b| {
-            Ord::cmp(
-                a.fields[self.identity_field.as_str()].to_string().as_str(),
-                b.fields[self.identity_field.as_str()].to_string().as_str(),
-            )
-        });
-        let mut it1 = sorted1.next();
-        let mut it2 = sorted2.next();
-        loop {
-            match (it1, it2) {
-                (Some(r1), Some(r2)) => {
-                    match Ord::cmp(
-                        r1.fields[self.identity_field.as_str()].to_string().as_str(),
-                        r2.fields[self.identity_field.as_str()].to_string().as_str(),
-                    ) {
-                        Ordering::Less => {
-                            differences.push(format!(
-                                "Extra in {}: {} - a",
-                                self.input1,
-                                r1.fields[self.identity_field.as_str()]
-                            ));
-                            it1 = sorted1.next();
-                        }
-                        Ordering::Greater => {
-                            differences.push(format!(
-                                "Extra in {}: {} - b",
-                                self.input2,
-                                r2.fields[self.identity_field.as_str()]
-                            ));
-                            it2 = sorted2.next();
-                        }
-                        Ordering::Equal => {
-                            //same key
-                            // find fields that are different
-                            for (k, v) in r1.fields.iter() {
-                                if r2.fields.contains_key(k) {
-                                    if r2.fields[k] != *v {
-                                        differences.push(format!(
-                                            "Mismatch: id: {}, field: {}, ({}) {} != ({}) {}",
-                                            r1.fields[self.identity_field.as_str()],
-                                            k,
-                                            self.input1,
-                                            v,
-                                            self.input2,
-                                            r2.fields[k]
-                                        ));
-                                    }
-                                } else {
-                                    differences.push(format!(
-                                        "Missing field in {}: id: {}, field: {}",
-                                        self.input2,
-                                        r1.fields[self.identity_field.as_str()],
-                                        k
-                                    ));
+    /// The differences as a json document, values of `input1` are reported as `old` and
+    /// the ones of `input2` as `new`.
+    pub fn report_json(&self, differences: &[Difference]) -> Value {
+        let mut only_in_left = vec![];
+        let mut only_in_right = vec![];
+        let mut missing_key_in_left = vec![];
+        let mut missing_key_in_right = vec![];
+        let mut changed = vec![];
+        let mut changed_fields = 0;
+        for difference in differences {
+            match difference {
+                Difference::MissingKeyInLeft(r1) => {
+                    missing_key_in_left.push(serde_json::to_value(r1).unwrap())
+                }
+                Difference::MissingKeyInRight(r2) => {
+                    missing_key_in_right.push(serde_json::to_value(r2).unwrap())
+                }
+                Difference::OnlyInLeft(r1) => only_in_left.push(serde_json::to_value(r1).unwrap()),
+                Difference::OnlyInRight(r2) => {
+                    only_in_right.push(serde_json::to_value(r2).unwrap())
+                }
+                Difference::Changed {
+                    left: r1,
+                    right: r2,
+                    fields,
+                } => {
+                    changed_fields += fields.len();
+                    let fields: Vec<Value> = fields
+                        .iter()
+                        .map(|field| match field {
+                            FieldDifference::Mismatch { left, right } => {
+                                let mut change = json!({
+                                    "field": left,
+                                    "status": "mismatch",
+                                    "old": r1.fields[*left].to_serde_value(),
+                                    "new": r2.fields[*right].to_serde_value(),
+                                });
+                                if left != right {
+                                    change["right_field"] = json!(right);
                                 }
+                                change
                             }
-                            // find fields that are in r2 but not in r1
-                            for (k, v) in r2.fields.iter() {
-                                if !r1.fields.contains_key(k) {
-                                    differences.push(format!(
-                                        "Missing field in {}: id: {}, field: {}",
-                                        self.input1,
-                                        r2.fields[self.identity_field.as_str()],
-                                        k
-                                    ));
+                            FieldDifference::MissingInRight(k) => json!({
+                                "field": k,
+                                "status": "missing_in_right",
+                                "old": r1.fields[*k].to_serde_value(),
+                                "new": null,
+                            }),
+                            FieldDifference::MissingInLeft(k) => {
+                                let left = self.options.left_name(k);
+                                let mut change = json!({
+                                    "field": left,
+                                    "status": "missing_in_left",
+                                    "old": null,
+                                    "new": r2.fields[*k].to_serde_value(),
+                                });
+                                if left != *k {
+                                    change["right_field"] = json!(k);
                                 }
+                                change
                             }
-                            it1 = sorted1.next();
-                            it2 = sorted2.next();
-                        }
+                        })
+                        .collect();
+                    changed.push(json!({
+                        "key": self.key_object(r1, Side::Left),
+                        "fields": fields,
+                    }));
+                }
+            }
+        }
+        json!({
+            "left": self.input1,
+            "right": self.input2,
+            "summary": {
+                "only_in_left": only_in_left.len(),
+                "only_in_right": only_in_right.len(),
+                "missing_key_in_left": missing_key_in_left.len(),
+                "missing_key_in_right": missing_key_in_right.len(),
+                "changed_records": changed.len(),
+                "changed_fields": changed_fields,
+            },
+            "only_in_left": only_in_left,
+            "only_in_right": only_in_right,
+            "missing_key_in_left": missing_key_in_left,
+            "missing_key_in_right": missing_key_in_right,
+            "changed": changed,
+        })
+    }
+
+    fn diff_record(
+        &self,
+        change: &str,
+        key: Option<(&Record, Side)>,
+        field: Option<&str>,
+        old: Option<&Variant>,
+        new: Option<&Variant>,
+    ) -> Record {
+        let mut fields = IndexMap::new();
+        fields.insert("change".to_string(), Variant::String(change.to_string()));
+        // prefixed, so that an identity field named like one of the other columns is kept
+        for f in &self.options.identity_fields {
+            let value = match key {
+                Some((record, side)) => record.fields[self.options.field_name(f, side)].clone(),
+                None => Variant::Null,
+            };
+            fields.insert(format!("key_{}", f), value);
+        }
+        fields.insert(
+            "field".to_string(),
+            field.map_or(Variant::Null, |f| Variant::String(f.to_string())),
+        );
+        fields.insert("old".to_string(), old.cloned().unwrap_or(Variant::Null));
+        fields.insert("new".to_string(), new.cloned().unwrap_or(Variant::Null));
+        Record { fields }
+    }
+
+    /// The differences as a table named after the node, one record per missing row or changed field,
+    /// with the identity fields in `key_` prefixed columns.
+    pub fn diff_table(&self, differences: &[Difference]) -> Table {
+        let mut records = vec![];
+        for difference in differences {
+            match difference {
+                Difference::MissingKeyInLeft(_) => {
+                    records.push(self.diff_record("missing_key_in_left", None, None, None, None))
+                }
+                Difference::MissingKeyInRight(_) => {
+                    records.push(self.diff_record("missing_key_in_right", None, None, None, None))
+                }
+                Difference::OnlyInLeft(r1) => records.push(self.diff_record(
+                    "only_in_left",
+                    Some((r1, Side::Left)),
+                    None,
+                    None,
+                    None,
+                )),
+                Difference::OnlyInRight(r2) => records.push(self.diff_record(
+                    "only_in_right",
+                    Some((r2, Side::Right)),
+                    None,
+                    None,
+                    None,
+                )),
+                Difference::Changed {
+                    left: r1,
+                    right: r2,
+                    fields,
+                } => {
+                    for field in fields {
+                        let key = Some((*r1, Side::Left));
+                        records.push(match field {
+                            FieldDifference::Mismatch { left, right } => self.diff_record(
+                                "mismatch",
+                                key,
+                                Some(left),
+                                r1.fields.get(*left),
+                                r2.fields.get(*right),
+                            ),
+                            FieldDifference::MissingInRight(k) => self.diff_record(
+                                "missing_in_right",
+                                key,
+                                Some(k),
+                                r1.fields.get(*k),
+                                None,
+                            ),
+                            FieldDifference::MissingInLeft(k) => self.diff_record(
+                                "missing_in_left",
+                                key,
+                                Some(self.options.left_name(k)),
+                                None,
+                                r2.fields.get(*k),
+                            ),
+                        });
                     }
                 }
-                (Some(r1), None) => {
-                    differences.push(format!(
-                        "Extra in {}: {} - c",
+            }
+        }
+        Table {
+            name: self.node_name.clone(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    // the two versions of a record next to each other, with the differing cells highlighted
+    fn html_side_by_side(
+        &self,
+        lines: &mut Vec<String>,
+        left: &Record,
+        right: &Record,
+        fields: &[FieldDifference],
+    ) {
+        let changed: Vec<&str> = fields
+            .iter()
+            .map(|f| match f {
+                FieldDifference::Mismatch { left, .. } => *left,
+                FieldDifference::MissingInRight(k) => *k,
+                FieldDifference::MissingInLeft(k) => self.options.left_name(k),
+            })
+            .collect();
+        let names: Vec<&str> = left
+            .fields
+            .keys()
+            .map(|k| k.as_str())
+            .chain(right.fields.keys().map(|k| self.options.left_name(k)))
+            .unique()
+            .collect();
+        lines.push(format!(
+            "<h3>{}</h3>",
+            escape_html(self.options.format_key(left, Side::Left).as_str())
+        ));
+        lines.push("<table>".to_string());
+        lines.push(format!(
+            "<tr><th>field</th><th>{}</th><th>{}</th></tr>",
+            escape_html(&self.input1),
+            escape_html(&self.input2)
+        ));
+        for name in names {
+            let v1 = left.fields.get(name);
+            let v2 = right.fields.get(self.options.right_name(name));
+            let class = |value: Option<&Variant>| {
+                if value.is_none() {
+                    " class=\"missing\""
+                } else if changed.contains(&name) {
+                    " class=\"changed\""
+                } else {
+                    ""
+                }
+            };
+            let right_name = self.options.right_name(name);
+            let label = if right_name == name {
+                escape_html(name)
+            } else {
+                format!("{}/{}", escape_html(name), escape_html(right_name))
+            };
+            lines.push(format!(
+                "<tr><th>{}</th><td{}>{}</td><td{}>{}</td></tr>",
+                label,
+                class(v1),
+                html_value(v1),
+                class(v2),
+                html_value(v2)
+            ));
+        }
+        lines.push("</table>".to_string());
+    }
+
+    /// The differences as a self-contained html page, with the summary counts, the rows found
+    /// on one side only and the mismatching records side by side.
+    pub fn report_html(&self, differences: &[Difference]) -> Vec<String> {
+        let mut only_in_left = vec![];
+        let mut only_in_right = vec![];
+        let mut missing_key_in_left = vec![];
+        let mut missing_key_in_right = vec![];
+        let mut changed = vec![];
+        for difference in differences {
+            match difference {
+                Difference::MissingKeyInLeft(r1) => missing_key_in_left.push(*r1),
+                Difference::MissingKeyInRight(r2) => missing_key_in_right.push(*r2),
+                Difference::OnlyInLeft(r1) => only_in_left.push(*r1),
+                Difference::OnlyInRight(r2) => only_in_right.push(*r2),
+                Difference::Changed {
+                    left,
+                    right,
+                    fields,
+                } => changed.push((*left, *right, fields)),
+            }
+        }
+        let title = format!(
+            "Comparison of {} and {}",
+            escape_html(&self.input1),
+            escape_html(&self.input2)
+        );
+        let mut lines = vec![
+            "<!DOCTYPE html>".to_string(),
+            "<html>".to_string(),
+            "<head>".to_string(),
+            "<meta charset=\"utf-8\">".to_string(),
+            format!("<title>{}</title>", title),
+            format!("<style>\n{}\n</style>", HTML_STYLE),
+            "</head>".to_string(),
+            "<body>".to_string(),
+            format!("<h1>{}</h1>", title),
+            "<h2>Summary</h2>".to_string(),
+            "<table>".to_string(),
+        ];
+        let summary = [
+            (format!("Only in {}", self.input1), only_in_left.len()),
+            (format!("Only in {}", self.input2), only_in_right.len()),
+            (
+                format!("Without identity in {}", self.input1),
+                missing_key_in_left.len(),
+            ),
+            (
+                format!("Without identity in {}", self.input2),
+                missing_key_in_right.len(),
+            ),
+            ("Mismatching records".to_string(), changed.len()),
+            (
+                "Mismatching fields".to_string(),
+                changed.iter().map(|(_, _, fields)| fields.len()).sum(),
+            ),
+        ];
+        for (label, count) in summary {
+            lines.push(format!(
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_html(&label),
+                count
+            ));
+        }
+        lines.push("</table>".to_string());
+        let sections = [
+            (format!("Only in {}", self.input1), &only_in_left),
+            (format!("Only in {}", self.input2), &only_in_right),
+            (
+                format!("Without identity in {}", self.input1),
+                &missing_key_in_left,
+            ),
+            (
+                format!("Without identity in {}", self.input2),
+                &missing_key_in_right,
+            ),
+        ];
+        for (label, records) in sections {
+            if !records.is_empty() {
+                lines.push(format!("<h2>{}</h2>", escape_html(&label)));
+                html_records(&mut lines, records);
+            }
+        }
+        if !changed.is_empty() {
+            lines.push("<h2>Mismatches</h2>".to_string());
+            for (left, right, fields) in changed {
+                self.html_side_by_side(&mut lines, left, right, fields);
+            }
+        }
+        lines.push("</body>".to_string());
+        lines.push("</html>".to_string());
+        lines
+    }
+
+    pub fn format_differences(&self, differences: &[Difference]) -> Vec<String> {
+        let id = |r: &Record, side: Side| self.options.format_key(r, side);
+        let mut lines: Vec<String> = vec![];
+        for difference in differences {
+            match difference {
+                Difference::MissingKeyInLeft(r1) => {
+                    lines.push(format!(
+                        "Missing identity field in {}: {}",
                         self.input1,
-                        r1.fields[self.identity_field.as_str()]
+                        serde_json::to_string(r1).unwrap()
                     ));
-                    it1 = sorted1.next();
                 }
-                (None, Some(r2)) => {
-                    differences.push(format!(
-                        "Extra in {}: {} - d",
+                Difference::MissingKeyInRight(r2) => {
+                    lines.push(format!(
+                        "Missing identity field in {}: {}",
                         self.input2,
-                        r2.fields[self.identity_field.as_str()]
+                        serde_json::to_string(r2).unwrap()
                     ));
-                    it2 = sorted2.next();
                 }
-                (None, None) => {
-                    break; //exit loop
+                Difference::OnlyInLeft(r1) => {
+                    lines.push(format!("Extra in {}: {}", self.input1, id(r1, Side::Left)));
+                }
+                Difference::OnlyInRight(r2) => {
+                    lines.push(format!("Extra in {}: {}", self.input2, id(r2, Side::Right)));
                 }
+                Difference::Changed {
+                    left: r1,
+                    right: r2,
+                    fields,
+                } => {
+                    for field in fields {
+                        lines.push(match field {
+                            FieldDifference::Mismatch { left, right } => format!(
+                                "Mismatch: id: {}, field: {}, ({}) {} != ({}) {}",
+                                id(r1, Side::Left),
+                                if left == right {
+                                    left.to_string()
+                                } else {
+                                    format!("{}/{}", left, right)
+                                },
+                                self.input1,
+                                r1.fields[*left],
+                                self.input2,
+                                r2.fields[*right]
+                            ),
+                            FieldDifference::MissingInRight(k) => format!(
+                                "Missing field in {}: id: {}, field: {}",
+                                self.input2,
+                                id(r1, Side::Left),
+                                k
+                            ),
+                            FieldDifference::MissingInLeft(k) => format!(
+                                "Missing field in {}: id: {}, field: {}",
+                                self.input1,
+                                id(r2, Side::Right),
+                                k
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl Process for OutputCompare {
+    register_process!(output::compare-table);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        OutputCompare {
+            node_name,
+            input1: read_config_field(&config, "input1"),
+            input2: read_config_field(&config, "input2"),
+            path: read_config_field(&config, "path"),
+            options: CompareOptions::from_config(&config),
+            format: ReportFormat::parse(
+                config
+                    .get("format")
+                    .and_then(|f| f.as_str())
+                    .unwrap_or("text"),
+            ),
+            save_table: config
+                .get("save_table")
+                .unwrap_or(&Value::Bool(false))
+                .as_bool()
+                .unwrap(),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let t1 = state.find_table(self.input1.as_str()).unwrap();
+        let t2 = state.find_table(self.input2.as_str()).unwrap();
+
+        let differences = diff_tables(t1, t2, &self.options);
+        let output = match self.format {
+            ReportFormat::Text => self.format_differences(&differences),
+            ReportFormat::Json => {
+                vec![serde_json::to_string_pretty(&self.report_json(&differences)).unwrap()]
             }
+            ReportFormat::Html => self.report_html(&differences),
+        };
+        let table = self.save_table.then(|| self.diff_table(&differences));
+        state.write_file(&self.path, &output);
+        if let Some(table) = table {
+            state.tables.push(table);
         }
-        let output = differences.join("\n");
-        std::fs::write(&self.path, output).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Variant;
+    use indexmap::IndexMap;
+
+    fn make_table(name: &str, rows: &[(i64, &str)]) -> Table {
+        let records = rows
+            .iter()
+            .map(|(id, name)| {
+                let mut fields = IndexMap::new();
+                fields.insert("id".to_string(), Variant::Int(*id));
+                fields.insert("name".to_string(), Variant::String(name.to_string()));
+                Record { fields }
+            })
+            .collect();
+        Table {
+            name: name.to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn make_compare() -> OutputCompare {
+        let config: Value = serde_json::from_str(
+            r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id"}"#,
+        )
+        .unwrap();
+        OutputCompare::from_config("test".to_string(), config.as_object().unwrap().clone())
+    }
+
+    #[test]
+    fn test_compare_tables() {
+        let left = make_table("left", &[(1, "Alice"), (2, "Bob"), (4, "Dave")]);
+        let mut right = make_table("right", &[(2, "Robert"), (3, "Carol"), (4, "Dave")]);
+        right.records[2]
+            .fields
+            .insert("age".to_string(), Variant::Int(40));
+        let compare = make_compare();
+        let differences = diff_tables(&left, &right, &compare.options);
+        assert_eq!(
+            compare.format_differences(&differences),
+            vec![
+                "Extra in left: 1",
+                "Mismatch: id: 2, field: name, (left) \"Bob\" != (right) \"Robert\"",
+                "Extra in right: 3",
+                "Missing field in left: id: 4, field: age",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_composite_key_and_missing_key() {
+        let mut left = make_table("left", &[(1, "Alice"), (1, "Bob"), (2, "Alice")]);
+        let mut right = make_table("right", &[(1, "Bob"), (2, "Alice"), (2, "Bob")]);
+        left.records[2]
+            .fields
+            .insert("age".to_string(), Variant::Int(30));
+        right.records[1]
+            .fields
+            .insert("age".to_string(), Variant::Int(31));
+        right.records[2].fields.shift_remove("name");
+        let config: Value = serde_json::from_str(
+            r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":["id","name"]}"#,
+        )
+        .unwrap();
+        let compare =
+            OutputCompare::from_config("test".to_string(), config.as_object().unwrap().clone());
+        let differences = diff_tables(&left, &right, &compare.options);
+        assert_eq!(
+            compare.format_differences(&differences),
+            vec![
+                r#"Missing identity field in right: {"id":2}"#,
+                r#"Extra in left: (1, "Alice")"#,
+                r#"Mismatch: id: (2, "Alice"), field: age, (left) 30 != (right) 31"#,
+            ]
+        );
+    }
+
+    fn compare_with(config: &str, left: &Table, right: &Table) -> Vec<String> {
+        let config: Value = serde_json::from_str(config).unwrap();
+        let compare =
+            OutputCompare::from_config("test".to_string(), config.as_object().unwrap().clone());
+        let differences = diff_tables(left, right, &compare.options);
+        compare.format_differences(&differences)
+    }
+
+    #[test]
+    fn test_integer_keys_sort_numerically() {
+        let left = make_table("left", &[(2, "Bob"), (10, "Jim"), (9, "Ian")]);
+        let right = make_table("right", &[(10, "Jim"), (2, "Bob"), (9, "Ian")]);
+        assert!(compare_with(
+            r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id"}"#,
+            &left,
+            &right
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_int_and_float_are_equal() {
+        let left = make_table("left", &[(1, "Alice")]);
+        let mut right = make_table("right", &[(1, "Alice")]);
+        right.records[0]
+            .fields
+            .insert("id".to_string(), Variant::Float(1.0));
+        assert!(compare_with(
+            r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id"}"#,
+            &left,
+            &right
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_tolerance_case_whitespace_and_coercion() {
+        let mut left = make_table("left", &[(1, "Alice  Smith"), (2, "Bob")]);
+        let mut right = make_table("right", &[(1, "alice smith"), (2, "BOB")]);
+        left.records[0]
+            .fields
+            .insert("price".to_string(), Variant::Float(10.0));
+        right.records[0]
+            .fields
+            .insert("price".to_string(), Variant::Float(10.004));
+        left.records[1]
+            .fields
+            .insert("price".to_string(), Variant::Float(10.0));
+        right.records[1]
+            .fields
+            .insert("price".to_string(), Variant::Float(10.2));
+        for record in right.records.iter_mut() {
+            let id = record.fields["id"].to_string();
+            record.fields.insert("id".to_string(), Variant::String(id));
+        }
+        let config = r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id",
+            "case_insensitive":true,"ignore_whitespace":true,"tolerance":0.01,
+            "column_rules":{"id":{"coerce":"number"}}}"#;
+        assert_eq!(
+            compare_with(config, &left, &right),
+            vec!["Mismatch: id: 2, field: price, (left) 10.0 != (right) 10.2"]
+        );
+        // without coercion the string ids do not match the integer ones
+        let strict =
+            r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id"}"#;
+        assert_eq!(compare_with(strict, &left, &right).len(), 4);
+    }
+
+    #[test]
+    fn test_tolerance_on_exact_numbers() {
+        let rules = ComparisonRules {
+            tolerance: 0.01,
+            ..ComparisonRules::default()
+        };
+        let decimal = |s: &str| Variant::Decimal(s.parse().unwrap());
+        assert!(rules.equal(&Variant::Int(100), &decimal("100.001")));
+        assert!(rules.equal(&decimal("10.015"), &decimal("10.005")));
+        assert!(!rules.equal(&decimal("100.02"), &Variant::Int(100)));
+        assert!(!rules.equal(&Variant::Int(1 << 53), &Variant::Int((1 << 53) + 1)));
+        assert!(rules.equal(&Variant::Float(10.0), &decimal("10.004")));
+        // without tolerance the values must be the same, f64 would round these together
+        let strict = ComparisonRules::default();
+        assert!(!strict.equal(&decimal("0.1000000000000000000001"), &decimal("0.1")));
+        assert!(!strict.equal(&Variant::Int(1 << 53), &Variant::Int((1 << 53) + 1)));
+        assert!(strict.equal(&Variant::Int(2), &decimal("2.000")));
+        assert!(!strict.equal(&Variant::Float(0.1 + 0.2), &Variant::Float(0.3)));
+        assert!(!strict.equal(&Variant::Float(10.0), &decimal("10.004")));
+    }
+
+    #[test]
+    fn test_ignore_and_map_fields() {
+        let mut left = make_table("left", &[(1, "Alice"), (2, "Bob")]);
+        let mut right = make_table("right", &[(1, "Alice"), (2, "Robert")]);
+        for (i, record) in left.records.iter_mut().enumerate() {
+            record
+                .fields
+                .insert("updated_at".to_string(), Variant::Int(i as i64));
+        }
+        for record in right.records.iter_mut() {
+            let name = record.fields.shift_remove("name").unwrap();
+            record.fields.insert("full_name".to_string(), name);
+            record
+                .fields
+                .insert("updated_at".to_string(), Variant::Int(100));
+        }
+        let config = r#"{"input1":"left","input2":"right","path":"diff.txt","identity_field":"id",
+            "ignore_fields":["updated_at"],"field_mapping":{"name":"full_name"}}"#;
+        assert_eq!(
+            compare_with(config, &left, &right),
+            vec![r#"Mismatch: id: 2, field: name/full_name, (left) "Bob" != (right) "Robert""#]
+        );
+    }
+
+    #[test]
+    fn test_json_report_and_diff_table() {
+        let left = make_table("left", &[(1, "Alice"), (2, "Bob")]);
+        let mut right = make_table("right", &[(2, "Robert"), (3, "Carol")]);
+        right.records[0]
+            .fields
+            .insert("age".to_string(), Variant::Int(40));
+        let config: Value = serde_json::from_str(
+            r#"{"input1":"left","input2":"right","path":"diff.json","identity_field":"id","format":"json","save_table":true}"#,
+        )
+        .unwrap();
+        let compare =
+            OutputCompare::from_config("diff".to_string(), config.as_object().unwrap().clone());
+        let differences = diff_tables(&left, &right, &compare.options);
+
+        let report = compare.report_json(&differences);
+        assert_eq!(
+            report["summary"],
+            json!({
+                "only_in_left": 1,
+                "only_in_right": 1,
+                "missing_key_in_left": 0,
+                "missing_key_in_right": 0,
+                "changed_records": 1,
+                "changed_fields": 2,
+            })
+        );
+        assert_eq!(report["only_in_left"], json!([{"id": 1, "name": "Alice"}]));
+        assert_eq!(
+            report["changed"],
+            json!([{
+                "key": {"id": 2},
+                "fields": [
+                    {"field": "name", "status": "mismatch", "old": "Bob", "new": "Robert"},
+                    {"field": "age", "status": "missing_in_left", "old": null, "new": 40},
+                ]
+            }])
+        );
+
+        let table = compare.diff_table(&differences);
+        assert_eq!(table.name, "diff");
+        assert_eq!(table.records.len(), 4);
+        let mismatch = &table.records[1].fields;
+        assert_eq!(mismatch["change"], Variant::String("mismatch".to_string()));
+        assert_eq!(mismatch["key_id"], Variant::Int(2));
+        assert_eq!(mismatch["old"], Variant::String("Bob".to_string()));
+        assert_eq!(mismatch["new"], Variant::String("Robert".to_string()));
+    }
+
+    #[test]
+    fn test_diff_table_identity_and_mapped_names() {
+        let mut left = make_table("left", &[(1, "Alice")]);
+        let mut right = make_table("right", &[(1, "Alice")]);
+        for record in left.records.iter_mut() {
+            let id = record.fields.shift_remove("id").unwrap();
+            record.fields.insert("field".to_string(), id);
+        }
+        for record in right.records.iter_mut() {
+            let id = record.fields.shift_remove("id").unwrap();
+            record.fields.insert("field".to_string(), id);
+            record.fields.insert("years".to_string(), Variant::Int(40));
+        }
+        let config: Value = serde_json::from_str(
+            r#"{"input1":"left","input2":"right","path":"diff.json","identity_field":"field",
+                "field_mapping":{"age":"years"}}"#,
+        )
+        .unwrap();
+        let compare =
+            OutputCompare::from_config("diff".to_string(), config.as_object().unwrap().clone());
+        let differences = diff_tables(&left, &right, &compare.options);
+
+        let table = compare.diff_table(&differences);
+        let change = &table.records[0].fields;
+        assert_eq!(change["key_field"], Variant::Int(1));
+        assert_eq!(change["field"], Variant::String("age".to_string()));
+        assert_eq!(change["new"], Variant::Int(40));
+        assert_eq!(
+            compare.report_json(&differences)["changed"][0]["fields"],
+            json!([{"field": "age", "right_field": "years", "status": "missing_in_left",
+                "old": null, "new": 40}])
+        );
+    }
+
+    #[test]
+    fn test_html_report() {
+        let left = make_table("left", &[(1, "Alice"), (2, "Bob"), (3, "<Carl>")]);
+        let right = make_table("right", &[(2, "Robert"), (3, "<Carl>"), (4, "Dan")]);
+        let config: Value = serde_json::from_str(
+            r#"{"input1":"left","input2":"right","path":"diff.html","identity_field":"id","format":"html"}"#,
+        )
+        .unwrap();
+        let compare =
+            OutputCompare::from_config("diff".to_string(), config.as_object().unwrap().clone());
+        let differences = diff_tables(&left, &right, &compare.options);
+        let html = compare.report_html(&differences).join("\n");
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr><th>Only in left</th><td>1</td></tr>"));
+        assert!(html.contains("<tr><th>Mismatching fields</th><td>1</td></tr>"));
+        assert!(html.contains("<h2>Only in right</h2>"));
+        assert!(html.contains("<tr><td>4</td><td>Dan</td></tr>"));
+        assert!(html.contains(
+            "<tr><th>name</th><td class=\"changed\">Bob</td><td class=\"changed\">Robert</td></tr>"
+        ));
+        assert!(html.contains("<tr><th>id</th><td>2</td><td>2</td></tr>"));
+        // equal records are not listed
+        assert!(!html.contains("&lt;Carl&gt;"));
+        assert!(!html.contains("<link") && !html.contains("<script"));
     }
 }
@@ -0,0 +1,246 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use itertools::Itertools;
+use serde_json::{Map, Value};
+
+/// Row terminator mandated by RFC 4180.
+const TERMINATOR: &str = "\r\n";
+
+pub struct OutputCsv {
+    input: String,
+    path: String,
+    columns: Option<Vec<String>>,
+    null: String,
+    delimiter: char,
+    header: bool,
+}
+
+impl OutputCsv {
+    fn quote(&self, value: &str) -> String {
+        let needs_quotes = value.contains(self.delimiter)
+            || value.contains('"')
+            || value.contains('\n')
+            || value.contains('\r')
+            // a string equal to the null marker, even an empty one, must not be read as NULL
+            || value == self.null;
+        if needs_quotes {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn format_value(&self, value: Option<&Variant>) -> String {
+        match value {
+            None | Some(Variant::Null) => self.null.clone(),
+            Some(value) => self.quote(value.to_string().as_str()),
+        }
+    }
+
+    fn column_names(&self, table: &Table) -> Vec<String> {
+        match &self.columns {
+            Some(columns) => columns.clone(),
+            None => table
+                .column_names()
+                .into_iter()
+                .map(|c| c.to_string())
+                .collect(),
+        }
+    }
+
+    fn format_record(&self, record: &Record, columns: &[String]) -> String {
+        columns
+            .iter()
+            .map(|c| self.format_value(record.fields.get(c)))
+            .join(self.delimiter.to_string().as_str())
+    }
+
+    pub fn format_table(&self, table: &Table) -> Vec<String> {
+        let columns = self.column_names(table);
+        let mut lines: Vec<String> = vec![];
+        if self.header {
+            lines.push(
+                columns
+                    .iter()
+                    .map(|c| self.quote(c))
+                    .join(self.delimiter.to_string().as_str()),
+            );
+        }
+        for record in &table.records {
+            lines.push(self.format_record(record, &columns));
+        }
+        lines.into_iter().map(|line| line + TERMINATOR).collect()
+    }
+}
+
+impl Process for OutputCsv {
+    register_process!(output::csv);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let columns = config.get("columns").map(|columns| {
+            columns
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c.as_str().unwrap().to_string())
+                .collect()
+        });
+        let delimiter = config
+            .get("delimiter")
+            .and_then(|d| d.as_str())
+            .unwrap_or(",");
+        if delimiter.chars().count() != 1 {
+            panic!("delimiter must be a single character");
+        }
+        OutputCsv {
+            input: read_config_field(&config, "input"),
+            path: read_config_field(&config, "path"),
+            columns,
+            null: config
+                .get("null")
+                .and_then(|n| n.as_str())
+                .unwrap_or("")
+                .to_string(),
+            delimiter: delimiter.chars().next().unwrap(),
+            header: config
+                .get("header")
+                .unwrap_or(&Value::Bool(true))
+                .as_bool()
+                .unwrap(),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        // rows carry their own terminator, so hand the writer a single chunk
+        let content = self.format_table(table).concat();
+        state.write_file(self.path.as_str(), &[content]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Config, MemoryReader, MemoryWriter};
+    use indexmap::IndexMap;
+
+    fn make_output(config: &str) -> OutputCsv {
+        let config: Value = serde_json::from_str(config).unwrap();
+        OutputCsv::from_config("out".to_string(), config.as_object().unwrap().clone())
+    }
+
+    fn make_table() -> Table {
+        let mut rec1 = Record {
+            fields: IndexMap::new(),
+        };
+        rec1.fields
+            .insert("name".to_string(), Variant::String("Alice".to_string()));
+        rec1.fields.insert("age".to_string(), Variant::Int(30));
+        let mut rec2 = Record {
+            fields: IndexMap::new(),
+        };
+        rec2.fields.insert(
+            "name".to_string(),
+            Variant::String("Bob \"the builder\", jr".to_string()),
+        );
+        rec2.fields.insert("age".to_string(), Variant::Null);
+        rec2.fields
+            .insert("note".to_string(), Variant::String("".to_string()));
+        Table {
+            name: "people".to_string(),
+            records: vec![rec1, rec2],
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_union_of_columns_in_record_order() {
+        let output = make_output(r#"{"input":"people","path":"out.csv"}"#);
+        let lines = output.format_table(&make_table());
+        assert_eq!(
+            lines,
+            vec![
+                "name,age,note\r\n",
+                "Alice,30,\r\n",
+                "\"Bob \"\"the builder\"\", jr\",,\"\"\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explicit_columns_null_and_delimiter() {
+        let output = make_output(
+            r#"{"input":"people","path":"out.csv","columns":["name","age"],"null":"NULL","delimiter":";","header":false}"#,
+        );
+        let lines = output.format_table(&make_table());
+        assert_eq!(
+            lines,
+            vec!["Alice;30\r\n", "\"Bob \"\"the builder\"\", jr\";NULL\r\n"]
+        );
+    }
+
+    #[test]
+    fn test_strings_equal_to_null_marker_are_quoted() {
+        let output = make_output(r#"{"input":"t","path":"out.csv","null":"NULL"}"#);
+        let mut rec = Record {
+            fields: IndexMap::new(),
+        };
+        rec.fields
+            .insert("word".to_string(), Variant::String("NULL".to_string()));
+        rec.fields.insert("missing".to_string(), Variant::Null);
+        rec.fields
+            .insert("empty".to_string(), Variant::String("".to_string()));
+        let table = Table {
+            name: "t".to_string(),
+            records: vec![rec],
+            columns: vec![],
+            schema: None,
+        };
+        assert_eq!(
+            output.format_table(&table),
+            vec!["word,missing,empty\r\n", "\"NULL\",NULL,\r\n"]
+        );
+    }
+
+    #[test]
+    fn test_multiline_values_are_quoted() {
+        let output = make_output(r#"{"input":"t","path":"out.csv"}"#);
+        let mut rec = Record {
+            fields: IndexMap::new(),
+        };
+        rec.fields
+            .insert("bio".to_string(), Variant::String("one\ntwo".to_string()));
+        let table = Table {
+            name: "t".to_string(),
+            records: vec![rec],
+            columns: vec![],
+            schema: None,
+        };
+        assert_eq!(
+            output.format_table(&table),
+            vec!["bio\r\n", "\"one\ntwo\"\r\n"]
+        );
+    }
+
+    #[test]
+    fn test_rows_are_crlf_terminated() {
+        let config = Config {
+            state_file: "state.json".to_string(),
+            template_file: "template.json".to_string(),
+        };
+        let pipeline = serde_json::from_str(r#"{"test":{"driver":"output::csv"}}"#).unwrap();
+        let mut state = State::make(
+            &config,
+            &pipeline,
+            Some(Box::new(MemoryWriter::new())),
+            Some(Box::new(MemoryReader::new())),
+        );
+        state.tables.push(make_table());
+        let output = make_output(r#"{"input":"people","path":"out.csv","columns":["name"]}"#);
+        output.run(&mut state);
+
+        assert_eq!(
+            state.results_writer.test_peek("out.csv").unwrap(),
+            &vec!["name\r\nAlice\r\n\"Bob \"\"the builder\"\", jr\"\r\n".to_string()]
+        );
+    }
+}
@@ -5,6 +5,28 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::result::Result;
 
+// the records as json objects with the fields in the order of the table columns
+pub fn ordered_records(table: &Table) -> Vec<Value> {
+    let columns = table.column_names();
+    table
+        .records
+        .iter()
+        .map(|record| {
+            Value::Object(
+                columns
+                    .iter()
+                    .filter_map(|c| {
+                        record
+                            .fields
+                            .get(*c)
+                            .map(|v| (c.to_string(), v.to_serde_value()))
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
 pub struct OutputJson {
     input: String,
     path: String,
@@ -22,6 +44,6 @@ impl Process for OutputJson {
         let table = state.find_table(&self.input).unwrap();
         let file = std::fs::File::create(&self.path).unwrap();
         let mut writer = std::io::BufWriter::new(file);
-        serde_json::to_writer_pretty(&mut writer, &table.records).unwrap();
+        serde_json::to_writer_pretty(&mut writer, &ordered_records(table)).unwrap();
     }
 }
@@ -7,8 +7,17 @@ pub use compare::*;
 mod json;
 pub use json::*;
 
+mod csv;
+pub use csv::*;
+
 mod rabbitmq;
 pub use rabbitmq::*;
 
 mod asserts;
 pub use asserts::*;
+
+mod sqlite;
+pub use sqlite::*;
+
+mod sync_sql;
+pub use sync_sql::*;
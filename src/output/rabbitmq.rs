@@ -6,7 +6,6 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::result::Result;
 // use futures_lite::stream::StreamExt;
-use async_global_executor;
 use lapin::{
     options::*, publisher_confirm::Confirmation, types::AMQPType, types::AMQPValue,
     types::FieldTable, BasicProperties, Connection, ConnectionProperties,
@@ -65,7 +64,7 @@ impl Process for OutputRabbitMQ {
                 let amqp_value = match value {
                     Value::String(s) => AMQPValue::try_from(value, AMQPType::LongString).unwrap(),
                     Value::Number(n) => AMQPValue::LongLongInt(n.as_i64().unwrap()),
-                    Value::Bool(b) => AMQPValue::Boolean(b.clone()),
+                    Value::Bool(b) => AMQPValue::Boolean(*b),
                     _ => AMQPValue::Void,
                 };
                 exchange_args.insert(key.clone().into(), amqp_value);
@@ -73,7 +72,7 @@ impl Process for OutputRabbitMQ {
 
             let exchange = channel_a
                 .queue_declare(
-                    &self.exchange.as_str(),
+                    self.exchange.as_str(),
                     QueueDeclareOptions::default(),
                     exchange_args,
                 )
@@ -1,4 +1,6 @@
-use crate::state::{read_config_field, Factory, Process, State, Table};
+use crate::state::{
+    read_config_field, read_fields_list, to_hex, Factory, Process, Record, State, Table, Variant,
+};
 use crate::register_process;
 use itertools::Itertools;
 use serde_json::{Map, Value};
@@ -14,38 +16,659 @@ where
         .join(joiner.unwrap_or(","))
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SqlDialect {
+    Mysql,
+    Postgres,
+    Sqlite,
+}
+
+impl SqlDialect {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "mysql" => SqlDialect::Mysql,
+            "postgres" => SqlDialect::Postgres,
+            "sqlite" => SqlDialect::Sqlite,
+            _ => panic!("Unknown sql dialect {}", value),
+        }
+    }
+}
+
+/// Renders identifiers and values as sql text for a specific dialect.
+#[derive(Debug, Clone, Copy)]
+pub struct SqlFormatter {
+    pub dialect: SqlDialect,
+    pub quote_identifiers: bool,
+}
+
+impl SqlFormatter {
+    pub fn from_config(config: &Map<String, Value>) -> Self {
+        SqlFormatter {
+            dialect: SqlDialect::parse(
+                config
+                    .get("dialect")
+                    .and_then(|d| d.as_str())
+                    .unwrap_or("mysql"),
+            ),
+            quote_identifiers: config
+                .get("quote_identifiers")
+                .unwrap_or(&Value::Bool(true))
+                .as_bool()
+                .unwrap(),
+        }
+    }
+
+    pub fn identifier(&self, name: &str) -> String {
+        if !self.quote_identifiers {
+            return name.to_string();
+        }
+        match self.dialect {
+            SqlDialect::Mysql => format!("`{}`", name.replace('`', "``")),
+            SqlDialect::Postgres | SqlDialect::Sqlite => {
+                format!("\"{}\"", name.replace('"', "\"\""))
+            }
+        }
+    }
+
+    /// Table names can be qualified with a schema or database name, every part is quoted on its own.
+    pub fn table(&self, name: &str) -> String {
+        join(name.split('.').map(|part| self.identifier(part)), Some("."))
+    }
+
+    pub fn string(&self, s: &str) -> String {
+        match self.dialect {
+            SqlDialect::Mysql => {
+                let mut escaped = String::with_capacity(s.len() + 2);
+                escaped.push('\'');
+                for c in s.chars() {
+                    match c {
+                        '\'' => escaped.push_str("\\'"),
+                        '\\' => escaped.push_str("\\\\"),
+                        '\0' => escaped.push_str("\\0"),
+                        '\n' => escaped.push_str("\\n"),
+                        '\r' => escaped.push_str("\\r"),
+                        '\x1a' => escaped.push_str("\\Z"),
+                        _ => escaped.push(c),
+                    }
+                }
+                escaped.push('\'');
+                escaped
+            }
+            // with standard_conforming_strings (the postgres default) backslashes are literal
+            SqlDialect::Postgres | SqlDialect::Sqlite => format!("'{}'", s.replace('\'', "''")),
+        }
+    }
+
+    pub fn literal(&self, value: &Variant) -> String {
+        match value {
+            Variant::Null => "NULL".to_string(),
+            Variant::Int(i) => i.to_string(),
+            Variant::Float(f) if f.is_finite() => f.to_string(),
+            Variant::Float(f) => match self.dialect {
+                SqlDialect::Postgres if f.is_nan() => "'NaN'".to_string(),
+                SqlDialect::Postgres if *f > 0.0 => "'Infinity'".to_string(),
+                SqlDialect::Postgres => "'-Infinity'".to_string(),
+                // mysql and sqlite have no representation for these
+                SqlDialect::Mysql | SqlDialect::Sqlite => "NULL".to_string(),
+            },
+            Variant::String(s) => self.string(s),
+            Variant::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+            Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_) => {
+                self.string(&value.to_string())
+            }
+            Variant::Decimal(d) => d.to_string(),
+            Variant::Bytes(b) => match self.dialect {
+                SqlDialect::Mysql | SqlDialect::Sqlite => format!("X'{}'", to_hex(b)),
+                SqlDialect::Postgres => format!("'\\x{}'", to_hex(b)),
+            },
+        }
+    }
+
+    fn assignments(&self, fields: &[(&str, &Variant)]) -> String {
+        join(
+            fields
+                .iter()
+                .map(|(name, value)| format!("{}={}", self.identifier(name), self.literal(value))),
+            None,
+        )
+    }
+
+    fn conditions(&self, fields: &[(&str, &Variant)]) -> String {
+        join(
+            fields.iter().map(|(name, value)| match value {
+                Variant::Null => format!("{} is NULL", self.identifier(name)),
+                _ => format!("{}={}", self.identifier(name), self.literal(value)),
+            }),
+            Some(" and "),
+        )
+    }
+
+    pub fn insert(&self, table: &str, columns: &[&str], rows: &[Vec<&Variant>]) -> String {
+        let values = join(
+            rows.iter()
+                .map(|row| format!("({})", join(row.iter().map(|v| self.literal(v)), None))),
+            None,
+        );
+        format!(
+            "insert into {} ({}) values {}",
+            self.table(table),
+            join(columns.iter().map(|c| self.identifier(c)), None),
+            values
+        )
+    }
+
+    /// An insert that updates the non-key columns when a row with the same key already exists.
+    pub fn upsert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: &[Vec<&Variant>],
+        keys: &[String],
+    ) -> String {
+        let insert = self.insert(table, columns, rows);
+        let mut updated: Vec<&str> = columns
+            .iter()
+            .filter(|c| !keys.iter().any(|k| k == *c))
+            .copied()
+            .collect();
+        match self.dialect {
+            SqlDialect::Mysql => {
+                // with nothing to update, re-assigning a key turns duplicates into no-ops
+                if updated.is_empty() {
+                    updated.push(keys[0].as_str());
+                }
+                format!(
+                    "{} on duplicate key update {}",
+                    insert,
+                    join(
+                        updated.iter().map(|c| format!(
+                            "{}=values({})",
+                            self.identifier(c),
+                            self.identifier(c)
+                        )),
+                        None
+                    )
+                )
+            }
+            SqlDialect::Postgres | SqlDialect::Sqlite => {
+                let conflict = join(keys.iter().map(|k| self.identifier(k)), None);
+                if updated.is_empty() {
+                    return format!("{} on conflict ({}) do nothing", insert, conflict);
+                }
+                format!(
+                    "{} on conflict ({}) do update set {}",
+                    insert,
+                    conflict,
+                    join(
+                        updated.iter().map(|c| format!(
+                            "{}=excluded.{}",
+                            self.identifier(c),
+                            self.identifier(c)
+                        )),
+                        None
+                    )
+                )
+            }
+        }
+    }
+
+    pub fn update(
+        &self,
+        table: &str,
+        set: &[(&str, &Variant)],
+        key: &[(&str, &Variant)],
+    ) -> String {
+        format!(
+            "update {} set {} where {}",
+            self.table(table),
+            self.assignments(set),
+            self.conditions(key)
+        )
+    }
+
+    pub fn delete(&self, table: &str, key: &[(&str, &Variant)]) -> String {
+        format!(
+            "delete from {} where {}",
+            self.table(table),
+            self.conditions(key)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SqlMode {
+    Insert,
+    Upsert,
+    Update,
+    Delete,
+}
+
+impl SqlMode {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "insert" => SqlMode::Insert,
+            "upsert" => SqlMode::Upsert,
+            "update" => SqlMode::Update,
+            "delete" => SqlMode::Delete,
+            _ => panic!("Unknown sql mode {}", value),
+        }
+    }
+}
+
 pub struct OutputSqlInserts {
     node_name: String,
     input: String,
     path: String,
     table_name: String,
+    formatter: SqlFormatter,
+    mode: SqlMode,
+    key_fields: Vec<String>,
+    batch_size: usize,
+}
+
+impl OutputSqlInserts {
+    fn key_values<'a>(&self, record: &'a Record) -> Vec<(&'a str, &'a Variant)> {
+        self.key_fields
+            .iter()
+            .map(|k| match record.fields.get_key_value(k) {
+                Some((name, value)) => (name.as_str(), value),
+                None => panic!("record is missing key field {}", k),
+            })
+            .collect()
+    }
+
+    fn non_key_values<'a>(
+        &self,
+        record: &'a Record,
+        columns: &[&'a str],
+    ) -> Vec<(&'a str, &'a Variant)> {
+        columns
+            .iter()
+            .filter(|name| !self.key_fields.iter().any(|k| k == *name))
+            .filter_map(|name| record.fields.get(*name).map(|value| (*name, value)))
+            .collect()
+    }
+
+    fn insert_statements(&self, records: &[Record], table_columns: &[&str]) -> Vec<String> {
+        // consecutive records with the same fields can share a single statement
+        let batches = records.chunk_by(|a, b| {
+            a.fields.len() == b.fields.len() && a.fields.keys().all(|k| b.fields.contains_key(k))
+        });
+        let mut commands: Vec<String> = vec![];
+        for records in batches {
+            let columns: Vec<&str> = table_columns
+                .iter()
+                .filter(|c| records[0].fields.contains_key(**c))
+                .cloned()
+                .collect();
+            for chunk in records.chunks(self.batch_size) {
+                let rows: Vec<Vec<&Variant>> = chunk
+                    .iter()
+                    .map(|r| columns.iter().map(|c| &r.fields[*c]).collect())
+                    .collect();
+                let command = match self.mode {
+                    SqlMode::Upsert => {
+                        self.formatter
+                            .upsert(&self.table_name, &columns, &rows, &self.key_fields)
+                    }
+                    _ => self.formatter.insert(&self.table_name, &columns, &rows),
+                };
+                commands.push(format!("{};", command));
+            }
+        }
+        commands
+    }
+
+    pub fn statements(&self, table: &Table) -> Vec<String> {
+        let columns = table.column_names();
+        // every value is written as a literal of the type of its column
+        let schema = table.resolved_schema();
+        let records: Vec<Record> = table
+            .records
+            .iter()
+            .map(|r| schema.cast_record(r))
+            .collect();
+        match self.mode {
+            SqlMode::Insert | SqlMode::Upsert => self.insert_statements(&records, &columns),
+            SqlMode::Update => records
+                .iter()
+                .filter_map(|r| {
+                    let set = self.non_key_values(r, &columns);
+                    // a record with only key fields has nothing to update
+                    if set.is_empty() {
+                        return None;
+                    }
+                    let key = self.key_values(r);
+                    Some(format!(
+                        "{};",
+                        self.formatter.update(&self.table_name, &set, &key)
+                    ))
+                })
+                .collect(),
+            SqlMode::Delete => records
+                .iter()
+                .map(|r| {
+                    let key = self.key_values(r);
+                    format!("{};", self.formatter.delete(&self.table_name, &key))
+                })
+                .collect(),
+        }
+    }
 }
 
 impl Process for OutputSqlInserts {
-    register_process!(output::sql-inserts);
+    register_process!(output::sql - inserts);
     fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let mode = SqlMode::parse(
+            config
+                .get("mode")
+                .and_then(|m| m.as_str())
+                .unwrap_or("insert"),
+        );
+        let key_fields = read_fields_list(&config, "key_fields");
+        if mode != SqlMode::Insert && key_fields.is_empty() {
+            panic!("key_fields is required for mode {:?}", mode);
+        }
         OutputSqlInserts {
             node_name,
             input: read_config_field(&config, "input"),
             path: read_config_field(&config, "path"),
             table_name: read_config_field(&config, "table-name"),
+            formatter: SqlFormatter::from_config(&config),
+            mode,
+            key_fields,
+            batch_size: config
+                .get("batch_size")
+                .and_then(|b| b.as_u64())
+                .unwrap_or(1)
+                .max(1) as usize,
         }
     }
     fn run(&self, state: &mut State) {
         let t = state.find_table(self.input.as_str()).unwrap();
+        let commands = self.statements(t);
+        state.write_file(&self.path, &commands);
+    }
+}
 
-        let mut commands: Vec<String> = vec![];
-        for record in &(t.records) {
-            let sorted_keys = record.fields.keys().sorted();
-            let fields_keys = join(sorted_keys.clone().map(|k| format!("`{}`", k)), None);
-            let fields_values = join(sorted_keys.map(|key| record.fields[key].to_string()), None);
-            commands.push(format!(
-                "insert into {} ({}) values ({});",
-                self.table_name, fields_keys, fields_values
-            ));
-        }
-        let output = join(commands.iter(), Some("\n"));
-
-        std::fs::write(&self.path, output).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ColumnSchema, Config, MemoryReader, MemoryWriter, Schema, VariantKind};
+    use indexmap::IndexMap;
+
+    fn make_process(config: &str) -> OutputSqlInserts {
+        let config: Value = serde_json::from_str(config).unwrap();
+        OutputSqlInserts::from_config("test".to_string(), config.as_object().unwrap().clone())
+    }
+
+    fn make_table(name: Variant) -> Table {
+        let mut rec = Record {
+            fields: IndexMap::new(),
+        };
+        rec.fields.insert("id".to_string(), Variant::Int(1));
+        rec.fields.insert("name".to_string(), name);
+        Table {
+            name: "people".to_string(),
+            records: vec![rec],
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_mysql_escaping() {
+        let process =
+            make_process(r#"{"input":"people","path":"out.sql","table-name":"db.people"}"#);
+        let table = make_table(Variant::String(
+            "O'Brien \\ \"the\"\nbuilder'); drop table people; --".to_string(),
+        ));
+        assert_eq!(
+            process.statements(&table),
+            vec![
+                r#"insert into `db`.`people` (`id`,`name`) values (1,'O\'Brien \\ "the"\nbuilder\'); drop table people; --');"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_postgres_escaping() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"public.people","dialect":"postgres"}"#,
+        );
+        let table = make_table(Variant::String("O'Brien \\ it's".to_string()));
+        assert_eq!(
+            process.statements(&table),
+            vec![r#"insert into "public"."people" ("id","name") values (1,'O''Brien \ it''s');"#]
+        );
+    }
+
+    #[test]
+    fn test_sqlite_null_and_unquoted_identifiers() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","dialect":"sqlite","quote_identifiers":false}"#,
+        );
+        let table = make_table(Variant::Null);
+        assert_eq!(
+            process.statements(&table),
+            vec!["insert into people (id,name) values (1,NULL);"]
+        );
+    }
+
+    #[test]
+    fn test_identifier_escaping() {
+        let mysql = SqlFormatter {
+            dialect: SqlDialect::Mysql,
+            quote_identifiers: true,
+        };
+        assert_eq!(mysql.identifier("we`ird"), "`we``ird`");
+        let postgres = SqlFormatter {
+            dialect: SqlDialect::Postgres,
+            quote_identifiers: true,
+        };
+        assert_eq!(postgres.identifier("we\"ird"), "\"we\"\"ird\"");
+        assert_eq!(postgres.literal(&Variant::Float(f64::NAN)), "'NaN'");
+        assert_eq!(mysql.literal(&Variant::Float(f64::INFINITY)), "NULL");
+        assert_eq!(mysql.literal(&Variant::Float(5.9)), "5.9");
+    }
+
+    #[test]
+    fn test_run_writes_file() {
+        let config = Config {
+            state_file: "state.json".to_string(),
+            template_file: "template.json".to_string(),
+        };
+        let pipeline =
+            serde_json::from_str(r#"{"test":{"driver":"output::sql-inserts"}}"#).unwrap();
+        let mut state = State::make(
+            &config,
+            &pipeline,
+            Some(Box::new(MemoryWriter::new())),
+            Some(Box::new(MemoryReader::new())),
+        );
+        state
+            .tables
+            .push(make_table(Variant::String("Alice".to_string())));
+        let process = make_process(r#"{"input":"people","path":"out.sql","table-name":"people"}"#);
+        process.run(&mut state);
+        assert_eq!(
+            state.results_writer.test_peek("out.sql").unwrap(),
+            &vec!["insert into `people` (`id`,`name`) values (1,'Alice');".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_typed_literals() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let values = [
+            Variant::Bool(false),
+            Variant::Date(date),
+            Variant::DateTime(date.and_hms_opt(10, 0, 0).unwrap()),
+            Variant::Decimal(rust_decimal::Decimal::new(-1050, 2)),
+            Variant::Bytes(vec![0xca, 0xfe]),
+        ];
+        let literals = |dialect| {
+            let formatter = SqlFormatter {
+                dialect,
+                quote_identifiers: true,
+            };
+            values
+                .iter()
+                .map(|v| formatter.literal(v))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            literals(SqlDialect::Mysql),
+            vec![
+                "FALSE",
+                "'2024-05-01'",
+                "'2024-05-01 10:00:00'",
+                "-10.50",
+                "X'cafe'"
+            ]
+        );
+        assert_eq!(literals(SqlDialect::Postgres)[4], "'\\xcafe'");
+    }
+
+    #[test]
+    fn test_literals_follow_the_schema() {
+        let process = make_process(r#"{"input":"people","path":"out.sql","table-name":"people"}"#);
+        let mut table = make_table(Variant::Int(7));
+        table.records[0]
+            .fields
+            .insert("id".to_string(), Variant::String("1".to_string()));
+        let column = |name: &str, kind| ColumnSchema {
+            name: name.to_string(),
+            kind,
+            nullable: false,
+            source_type: None,
+        };
+        table.schema = Some(Schema {
+            columns: vec![
+                column("id", VariantKind::Int),
+                column("name", VariantKind::String),
+            ],
+        });
+        assert_eq!(
+            process.statements(&table),
+            vec!["insert into `people` (`id`,`name`) values (1,'7');"]
+        );
+
+        // without a schema the kind of a column is inferred from all of its values
+        table.schema = None;
+        let mut rec = Record {
+            fields: IndexMap::new(),
+        };
+        rec.fields.insert("id".to_string(), Variant::Int(2));
+        rec.fields
+            .insert("name".to_string(), Variant::String("Bob".to_string()));
+        table.records.push(rec);
+        assert_eq!(
+            process.statements(&table),
+            vec![
+                "insert into `people` (`id`,`name`) values ('1','7');",
+                "insert into `people` (`id`,`name`) values ('2','Bob');",
+            ]
+        );
+    }
+
+    fn make_people() -> Table {
+        let mut records = vec![];
+        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Carol")] {
+            let mut rec = Record {
+                fields: IndexMap::new(),
+            };
+            rec.fields.insert("id".to_string(), Variant::Int(id));
+            rec.fields
+                .insert("name".to_string(), Variant::String(name.to_string()));
+            records.push(rec);
+        }
+        Table {
+            name: "people".to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_batched_inserts() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","batch_size":2}"#,
+        );
+        assert_eq!(
+            process.statements(&make_people()),
+            vec![
+                "insert into `people` (`id`,`name`) values (1,'Alice'),(2,'Bob');",
+                "insert into `people` (`id`,`name`) values (3,'Carol');",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mysql_upsert() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","mode":"upsert","key_fields":"id","batch_size":3}"#,
+        );
+        assert_eq!(
+            process.statements(&make_people()),
+            vec!["insert into `people` (`id`,`name`) values (1,'Alice'),(2,'Bob'),(3,'Carol') on duplicate key update `name`=values(`name`);"]
+        );
+    }
+
+    #[test]
+    fn test_postgres_upsert() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","mode":"upsert","key_fields":["id"],"dialect":"postgres"}"#,
+        );
+        assert_eq!(
+            process.statements(&make_people())[0],
+            r#"insert into "people" ("id","name") values (1,'Alice') on conflict ("id") do update set "name"=excluded."name";"#
+        );
+    }
+
+    #[test]
+    fn test_update_with_composite_key() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","mode":"update","key_fields":["id","name"]}"#,
+        );
+        let mut table = make_people();
+        table.records[0]
+            .fields
+            .insert("age".to_string(), Variant::Int(30));
+        table.records[0]
+            .fields
+            .insert("city".to_string(), Variant::Null);
+        assert_eq!(
+            process.statements(&table)[0],
+            "update `people` set `age`=30,`city`=NULL where `id`=1 and `name`='Alice';"
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let process = make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","mode":"delete","key_fields":"id","dialect":"sqlite"}"#,
+        );
+        let mut table = make_people();
+        table.records[2]
+            .fields
+            .insert("id".to_string(), Variant::Null);
+        assert_eq!(
+            process.statements(&table),
+            vec![
+                r#"delete from "people" where "id"=1;"#,
+                r#"delete from "people" where "id"=2;"#,
+                r#"delete from "people" where "id" is NULL;"#,
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "key_fields is required")]
+    fn test_update_requires_key_fields() {
+        make_process(
+            r#"{"input":"people","path":"out.sql","table-name":"people","mode":"update"}"#,
+        );
     }
 }
@@ -0,0 +1,259 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, State, Table, Variant, VariantKind};
+use itertools::Itertools;
+use rusqlite::{params_from_iter, Connection};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn affinity(kind: VariantKind) -> &'static str {
+    match kind {
+        VariantKind::Int | VariantKind::Bool => "INTEGER",
+        VariantKind::Float => "REAL",
+        // sqlite has no date or decimal types, they are stored as text
+        VariantKind::String
+        | VariantKind::Date
+        | VariantKind::DateTime
+        | VariantKind::Time
+        | VariantKind::Decimal => "TEXT",
+        VariantKind::Bytes => "BLOB",
+        VariantKind::Null => "",
+    }
+}
+
+// picks the sqlite column affinity that can hold every value of the column without conversions
+fn column_type(table: &Table, column: &str) -> &'static str {
+    // the type known from the source wins over the values
+    if let Some(column) = table.schema.as_ref().and_then(|s| s.column(column)) {
+        return affinity(column.kind);
+    }
+    let kinds: Vec<VariantKind> = table
+        .records
+        .iter()
+        .filter_map(|r| r.fields.get(column))
+        .filter(|v| **v != Variant::Null)
+        .map(VariantKind::of)
+        .unique()
+        .collect();
+    match kinds.as_slice() {
+        [kind] => affinity(*kind),
+        [VariantKind::Int, VariantKind::Float] | [VariantKind::Float, VariantKind::Int] => "REAL",
+        // mixed or all-null columns get no affinity, so sqlite stores values as they are
+        _ => "",
+    }
+}
+
+pub struct OutputSqlite {
+    input: String,
+    path: String,
+    table_name: String,
+}
+
+impl OutputSqlite {
+    pub fn write_table(&self, table: &Table) {
+        let mut columns = table.column_names();
+        if columns.is_empty() {
+            // a table without records still has the columns described by its source
+            if let Some(schema) = &table.schema {
+                columns = schema.columns.iter().map(|c| c.name.as_str()).collect();
+            }
+        }
+
+        let mut conn = Connection::open(self.path.as_str()).unwrap();
+        let transaction = conn.transaction().unwrap();
+        let table_name = quote_identifier(self.table_name.as_str());
+        // the previous content is always replaced, even by nothing
+        transaction
+            .execute(format!("drop table if exists {}", table_name).as_str(), [])
+            .unwrap();
+        if columns.is_empty() {
+            warn!(
+                "table {} has no columns, {} is dropped and not created",
+                table.name, self.table_name
+            );
+            transaction.commit().unwrap();
+            return;
+        }
+        let definitions = columns
+            .iter()
+            .map(|c| format!("{} {}", quote_identifier(c), column_type(table, c)))
+            .map(|d| d.trim_end().to_string())
+            .join(", ");
+        transaction
+            .execute(
+                format!("create table {} ({})", table_name, definitions).as_str(),
+                [],
+            )
+            .unwrap();
+        {
+            let insert = format!(
+                "insert into {} ({}) values ({})",
+                table_name,
+                columns.iter().map(|c| quote_identifier(c)).join(", "),
+                columns.iter().map(|_| "?").join(", ")
+            );
+            let mut statement = transaction.prepare(insert.as_str()).unwrap();
+            for record in &table.records {
+                let values = columns.iter().map(|c| match record.fields.get(*c) {
+                    Some(value) => value.to_sqlite_value(),
+                    None => Variant::Null.to_sqlite_value(),
+                });
+                statement.execute(params_from_iter(values)).unwrap();
+            }
+        }
+        transaction.commit().unwrap();
+    }
+}
+
+impl Process for OutputSqlite {
+    register_process!(output::sqlite);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        OutputSqlite {
+            input: read_config_field(&config, "input"),
+            path: read_config_field(&config, "path"),
+            table_name: read_config_field(&config, "table-name"),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        self.write_table(table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputSqlite;
+    use crate::state::{Record, Schema};
+    use indexmap::IndexMap;
+
+    fn temp_db(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("data-plumber-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    fn make_config(config: &str) -> Map<String, Value> {
+        let config: Value = serde_json::from_str(config).unwrap();
+        config.as_object().unwrap().clone()
+    }
+
+    fn make_table() -> Table {
+        let mut rec1 = Record {
+            fields: IndexMap::new(),
+        };
+        rec1.fields.insert("id".to_string(), Variant::Int(1));
+        rec1.fields
+            .insert("name".to_string(), Variant::String("O'Brien".to_string()));
+        rec1.fields
+            .insert("height".to_string(), Variant::Float(1.8));
+        let mut rec2 = Record {
+            fields: IndexMap::new(),
+        };
+        rec2.fields.insert("id".to_string(), Variant::Int(2));
+        rec2.fields.insert("name".to_string(), Variant::Null);
+        Table {
+            name: "people".to_string(),
+            records: vec![rec1, rec2],
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_db("round-trip");
+        let output = OutputSqlite::from_config(
+            "out".to_string(),
+            make_config(&format!(
+                r#"{{"input":"people","path":"{}","table-name":"people"}}"#,
+                path
+            )),
+        );
+        output.write_table(&make_table());
+        // writing twice replaces the table instead of appending to it
+        output.write_table(&make_table());
+
+        let input = InputSqlite::from_config(
+            "people".to_string(),
+            make_config(&format!(
+                r#"{{"path":"{}","query":"select * from people order by id"}}"#,
+                path
+            )),
+        );
+        let (columns, records) = input.read_records();
+        // the table keeps the order of the fields of the records
+        assert_eq!(columns, vec!["id", "name", "height"]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields["id"], Variant::Int(1));
+        assert_eq!(
+            records[0].fields["name"],
+            Variant::String("O'Brien".to_string())
+        );
+        assert_eq!(records[0].fields["height"], Variant::Float(1.8));
+        assert_eq!(records[1].fields["name"], Variant::Null);
+        assert_eq!(records[1].fields["height"], Variant::Null);
+
+        let conn = Connection::open(path.as_str()).unwrap();
+        let sql: String = conn
+            .query_row(
+                "select sql from sqlite_master where name = 'people'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            sql,
+            r#"CREATE TABLE "people" ("id" INTEGER, "name" TEXT, "height" REAL)"#
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn table_sql(path: &str) -> Option<String> {
+        let conn = Connection::open(path).unwrap();
+        conn.query_row(
+            "select sql from sqlite_master where name = 'people'",
+            [],
+            |row| row.get(0),
+        )
+        .ok()
+    }
+
+    #[test]
+    fn test_empty_tables_replace_previous_content() {
+        let path = temp_db("empty");
+        let output = OutputSqlite::from_config(
+            "out".to_string(),
+            make_config(&format!(
+                r#"{{"input":"people","path":"{}","table-name":"people"}}"#,
+                path
+            )),
+        );
+        output.write_table(&make_table());
+
+        // no records, but the columns are known from the schema
+        let mut empty = make_table();
+        empty.schema = Some(Schema::infer(&empty));
+        empty.records.clear();
+        output.write_table(&empty);
+        assert_eq!(
+            table_sql(&path).unwrap(),
+            r#"CREATE TABLE "people" ("id" INTEGER, "name" TEXT, "height" REAL)"#
+        );
+        let conn = Connection::open(path.as_str()).unwrap();
+        let count: i64 = conn
+            .query_row("select count(*) from people", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // without any column there is nothing to create, the stale table is still dropped
+        empty.schema = None;
+        output.write_table(&empty);
+        assert_eq!(table_sql(&path), None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use crate::output::{diff_tables, CompareOptions, Difference, FieldDifference, Side, SqlFormatter};
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Variant};
+use itertools::Itertools;
+use serde_json::{Map, Value};
+
+/// Generates the statements that make the `target` table match the `source` one,
+/// to be reviewed and applied manually.
+pub struct OutputSyncSql {
+    node_name: String,
+    source: String,
+    target: String,
+    path: String,
+    table_name: String,
+    options: CompareOptions,
+    formatter: SqlFormatter,
+}
+
+impl OutputSyncSql {
+    /// The key condition on the target table, with values taken from a record of either side.
+    fn key<'a>(&'a self, record: &'a Record, side: Side) -> Vec<(&'a str, &'a Variant)> {
+        self.options
+            .identity_fields
+            .iter()
+            .map(|f| {
+                let value = &record.fields[self.options.field_name(f, side)];
+                (self.options.right_name(f), value)
+            })
+            .collect()
+    }
+
+    pub fn statements(&self, differences: &[Difference]) -> Vec<String> {
+        let mut deletes: Vec<String> = vec![];
+        let mut updates: Vec<String> = vec![];
+        let mut inserts: Vec<String> = vec![];
+        let mut skipped: Vec<String> = vec![];
+        for difference in differences {
+            match difference {
+                Difference::MissingKeyInLeft(record) | Difference::MissingKeyInRight(record) => {
+                    skipped.push(format!(
+                        "-- skipped record without identity fields: {}",
+                        serde_json::to_string(record).unwrap()
+                    ));
+                }
+                Difference::OnlyInLeft(source) => {
+                    let fields: Vec<&str> = source
+                        .fields
+                        .keys()
+                        .map(|k| k.as_str())
+                        .filter(|k| !self.options.is_ignored(k))
+                        .collect();
+                    let columns: Vec<&str> =
+                        fields.iter().map(|f| self.options.right_name(f)).collect();
+                    let row: Vec<&Variant> = fields.iter().map(|f| &source.fields[*f]).collect();
+                    inserts.push(format!(
+                        "{};",
+                        self.formatter.insert(&self.table_name, &columns, &[row])
+                    ));
+                }
+                Difference::OnlyInRight(target) => {
+                    deletes.push(format!(
+                        "{};",
+                        self.formatter
+                            .delete(&self.table_name, &self.key(target, Side::Right))
+                    ));
+                }
+                Difference::Changed {
+                    left,
+                    right,
+                    fields,
+                } => {
+                    // fields that only exist in the target are left untouched
+                    let set: Vec<(&str, &Variant)> = fields
+                        .iter()
+                        .filter_map(|field| match field {
+                            FieldDifference::Mismatch { left: k, right } => {
+                                Some((*right, &left.fields[*k]))
+                            }
+                            FieldDifference::MissingInRight(k) => {
+                                Some((self.options.right_name(k), &left.fields[*k]))
+                            }
+                            FieldDifference::MissingInLeft(_) => None,
+                        })
+                        .collect();
+                    if !set.is_empty() {
+                        updates.push(format!(
+                            "{};",
+                            self.formatter.update(
+                                &self.table_name,
+                                &set,
+                                // the target's own key values, the rules may match them loosely
+                                &self.key(right, Side::Right)
+                            )
+                        ));
+                    }
+                }
+            }
+        }
+        // deleting first avoids clashes between removed and newly inserted unique values
+        skipped
+            .into_iter()
+            .chain(deletes)
+            .chain(updates)
+            .chain(inserts)
+            .collect()
+    }
+}
+
+impl Process for OutputSyncSql {
+    register_process!(output::sync-sql);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        OutputSyncSql {
+            node_name,
+            source: read_config_field(&config, "source"),
+            target: read_config_field(&config, "target"),
+            path: read_config_field(&config, "path"),
+            table_name: read_config_field(&config, "table-name"),
+            options: CompareOptions::from_config(&config),
+            formatter: SqlFormatter::from_config(&config),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let source = state.find_table(self.source.as_str()).unwrap();
+        let target = state.find_table(self.target.as_str()).unwrap();
+        let differences = diff_tables(source, target, &self.options);
+        let statements = self.statements(&differences);
+        state.write_file(&self.path, &statements);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Table;
+    use indexmap::IndexMap;
+
+    fn make_table(name: &str, rows: &[(i64, &str)]) -> Table {
+        let records = rows
+            .iter()
+            .map(|(id, name)| {
+                let mut fields = IndexMap::new();
+                fields.insert("id".to_string(), Variant::Int(*id));
+                fields.insert("name".to_string(), Variant::String(name.to_string()));
+                Record { fields }
+            })
+            .collect();
+        Table {
+            name: name.to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_sync_statements() {
+        let config: Value = serde_json::from_str(
+            r#"{"source":"dev","target":"demo","path":"sync.sql","table-name":"people","identity_field":"id"}"#,
+        )
+        .unwrap();
+        let process =
+            OutputSyncSql::from_config("sync".to_string(), config.as_object().unwrap().clone());
+        let source = make_table("dev", &[(1, "Alice"), (2, "O'Brien"), (4, "Dave")]);
+        let target = make_table("demo", &[(2, "Bob"), (3, "Carol"), (4, "Dave")]);
+        let differences = diff_tables(&source, &target, &process.options);
+        assert_eq!(
+            process.statements(&differences),
+            vec![
+                "delete from `people` where `id`=3;",
+                "update `people` set `name`='O\\'Brien' where `id`=2;",
+                "insert into `people` (`id`,`name`) values (1,'Alice');",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_with_mapped_and_ignored_fields() {
+        let config: Value = serde_json::from_str(
+            r#"{"source":"dev","target":"demo","path":"sync.sql","table-name":"people","identity_field":"id",
+                "field_mapping":{"id":"person_id","name":"full_name"},"ignore_fields":["updated_at"]}"#,
+        )
+        .unwrap();
+        let process =
+            OutputSyncSql::from_config("sync".to_string(), config.as_object().unwrap().clone());
+        let mut source = make_table("dev", &[(1, "Alice"), (2, "Bob")]);
+        for record in source.records.iter_mut() {
+            record
+                .fields
+                .insert("updated_at".to_string(), Variant::Int(100));
+        }
+        let mut target = make_table("demo", &[(2, "Robert"), (3, "Carol")]);
+        for record in target.records.iter_mut() {
+            let id = record.fields.shift_remove("id").unwrap();
+            let name = record.fields.shift_remove("name").unwrap();
+            record.fields.insert("person_id".to_string(), id);
+            record.fields.insert("full_name".to_string(), name);
+            record
+                .fields
+                .insert("updated_at".to_string(), Variant::Int(200));
+        }
+        let differences = diff_tables(&source, &target, &process.options);
+        assert_eq!(
+            process.statements(&differences),
+            vec![
+                "delete from `people` where `person_id`=3;",
+                "update `people` set `full_name`='Bob' where `person_id`=2;",
+                "insert into `people` (`person_id`,`full_name`) values (1,'Alice');",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_uses_target_key() {
+        let config: Value = serde_json::from_str(
+            r#"{"source":"dev","target":"demo","path":"sync.sql","table-name":"people","identity_field":"name",
+                "case_insensitive":true}"#,
+        )
+        .unwrap();
+        let process =
+            OutputSyncSql::from_config("sync".to_string(), config.as_object().unwrap().clone());
+        let source = make_table("dev", &[(1, "ALICE")]);
+        let target = make_table("demo", &[(2, "alice")]);
+        let differences = diff_tables(&source, &target, &process.options);
+        assert_eq!(
+            process.statements(&differences),
+            vec!["update `people` set `id`=1 where `name`='alice';"]
+        );
+    }
+}
@@ -1,15 +1,16 @@
-use crate::state::{Config, Variant};
+use crate::state::{Config, Schema, Variant};
+use indexmap::IndexMap;
+use itertools::Itertools;
 use mysql::Value as MysqlValue;
 use serde::de::{MapAccess, Visitor};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{Number as SerdeNumber, Value as SerdeValue};
-use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 
 #[derive(Debug)]
 pub struct Record {
-    pub fields: HashMap<String, Variant>,
+    pub fields: IndexMap<String, Variant>,
 }
 
 impl Serialize for Record {
@@ -46,7 +47,7 @@ impl<'de> Deserialize<'de> for Record {
             where
                 V: MapAccess<'de>,
             {
-                let mut fields = HashMap::new();
+                let mut fields = IndexMap::new();
                 while let Some((key, value)) = map.next_entry()? {
                     fields.insert(key, value);
                 }
@@ -62,6 +63,37 @@ impl<'de> Deserialize<'de> for Record {
 pub struct Table {
     pub name: String,
     pub records: Vec<Record>,
+    /// the order of the columns, as read from the source; empty when the order of the fields
+    /// in the records is enough
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+    /// the types of the columns, when known from the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+}
+
+impl Table {
+    /// The names of the fields of the records, in the order of `columns`, then in the order
+    /// they first appear in the records.
+    pub fn column_names(&self) -> Vec<&str> {
+        let others = self
+            .records
+            .iter()
+            .flat_map(|r| r.fields.keys())
+            .filter(|k| !self.columns.contains(k))
+            .unique()
+            .map(|k| k.as_str());
+        self.columns
+            .iter()
+            .map(|c| c.as_str())
+            .chain(others)
+            .collect()
+    }
+
+    /// The schema of the source when known, otherwise the one inferred from the records.
+    pub fn resolved_schema(&self) -> Schema {
+        self.schema.clone().unwrap_or_else(|| Schema::infer(self))
+    }
 }
 
 #[cfg(test)]
@@ -71,7 +103,7 @@ mod tests {
 
     #[test]
     fn test_serialize_json_string() {
-        let mut fields = HashMap::new();
+        let mut fields = IndexMap::new();
         fields.insert("name".to_string(), Variant::String("Alice".to_string()));
 
         let record = Record { fields };
@@ -84,7 +116,7 @@ mod tests {
 
     #[test]
     fn test_serialize_json_int() {
-        let mut fields = HashMap::new();
+        let mut fields = IndexMap::new();
         fields.insert("age".to_string(), Variant::Int(30));
 
         let record = Record { fields };
@@ -97,7 +129,7 @@ mod tests {
 
     #[test]
     fn test_serialize_json_float() {
-        let mut fields = HashMap::new();
+        let mut fields = IndexMap::new();
         fields.insert("height".to_string(), Variant::Float(5.9));
 
         let record = Record { fields };
@@ -111,7 +143,7 @@ mod tests {
     #[test]
     fn test_serialize_deserialize_record() {
         // Create a sample Record to test
-        let mut fields = HashMap::new();
+        let mut fields = IndexMap::new();
         fields.insert("age".to_string(), Variant::Int(30));
         fields.insert("name".to_string(), Variant::String("Alice".to_string()));
         fields.insert("height".to_string(), Variant::Float(5.9));
@@ -137,4 +169,16 @@ mod tests {
             Some(&Variant::Float(5.9))
         );
     }
+
+    #[test]
+    fn test_field_order_is_preserved() {
+        let serialized = r#"{"name":"people","records":[{"zip":"01234","name":"Alice","age":30},{"name":"Bob","email":"bob@x"}],"columns":["zip","name","age"]}"#;
+        let table: Table = serde_json::from_str(serialized).expect("Failed to deserialize");
+        let fields: Vec<&String> = table.records[0].fields.keys().collect();
+        assert_eq!(fields, vec!["zip", "name", "age"]);
+        assert_eq!(table.column_names(), vec!["zip", "name", "age", "email"]);
+
+        // saving the table again gives back the same text
+        assert_eq!(serde_json::to_string(&table).unwrap(), serialized);
+    }
 }
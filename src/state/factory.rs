@@ -9,6 +9,19 @@ pub fn read_config_field(config: &Map<String, Value>, name: &str) -> String {
     String::from(config[name].as_str().unwrap())
 }
 
+/// Reads a list of field names that can also be given as a single string.
+pub fn read_fields_list(config: &Map<String, Value>, name: &str) -> Vec<String> {
+    match config.get(name) {
+        None => vec![],
+        Some(Value::String(field)) => vec![field.clone()],
+        Some(Value::Array(fields)) => fields
+            .iter()
+            .map(|f| f.as_str().unwrap().to_string())
+            .collect(),
+        Some(_) => panic!("{} must be a string or an array of strings", name),
+    }
+}
+
 pub trait Process {
     fn register(factory: &mut Factory)
     where
@@ -30,9 +43,10 @@ macro_rules! register_process {
     };
 }
 
+type ProcessConstructor = Box<dyn Fn(String, Map<String, Value>) -> Box<dyn Process + 'static>>;
+
 pub struct Factory {
-    registry:
-        HashMap<String, Box<dyn Fn(String, Map<String, Value>) -> Box<dyn Process + 'static>>>,
+    registry: HashMap<String, ProcessConstructor>,
     templates: serde_json::Map<String, Value>,
 }
 impl Factory {
@@ -90,7 +104,7 @@ impl Factory {
         let constructor = self
             .registry
             .get(driver)
-            .expect(format!("Unknown driver {}", driver).as_str());
+            .unwrap_or_else(|| panic!("Unknown driver {}", driver));
         Some(constructor(node_name, config))
     }
 }
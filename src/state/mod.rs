@@ -1,12 +1,16 @@
 mod data;
 pub use data::*;
 
+#[allow(clippy::module_inception)]
 mod state;
 pub use state::*;
 
 mod variant;
 pub use variant::*;
 
+mod schema;
+pub use schema::*;
+
 mod factory;
 pub use factory::*;
 
@@ -0,0 +1,291 @@
+use crate::state::{from_hex, Record, Table, Variant, DATETIME_FORMAT, DATE_FORMAT, TIME_FORMAT};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The kind of `Variant` the values of a column are read as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariantKind {
+    /// a column without any non-null value, its kind is unknown
+    Null,
+    String,
+    Float,
+    Int,
+    Bool,
+    Date,
+    DateTime,
+    Time,
+    Decimal,
+    Bytes,
+}
+
+impl VariantKind {
+    pub fn of(value: &Variant) -> Self {
+        match value {
+            Variant::Null => VariantKind::Null,
+            Variant::String(_) => VariantKind::String,
+            Variant::Float(_) => VariantKind::Float,
+            Variant::Int(_) => VariantKind::Int,
+            Variant::Bool(_) => VariantKind::Bool,
+            Variant::Date(_) => VariantKind::Date,
+            Variant::DateTime(_) => VariantKind::DateTime,
+            Variant::Time(_) => VariantKind::Time,
+            Variant::Decimal(_) => VariantKind::Decimal,
+            Variant::Bytes(_) => VariantKind::Bytes,
+        }
+    }
+
+    /// Whether json has a type for the values of this kind, the others are saved as strings.
+    pub fn is_json_type(&self) -> bool {
+        matches!(
+            self,
+            VariantKind::Null
+                | VariantKind::String
+                | VariantKind::Float
+                | VariantKind::Int
+                | VariantKind::Bool
+        )
+    }
+
+    /// The narrowest kind that can hold the values of both kinds.
+    pub fn merge(self, other: VariantKind) -> Self {
+        match (self, other) {
+            (VariantKind::Null, kind) | (kind, VariantKind::Null) => kind,
+            (k1, k2) if k1 == k2 => k1,
+            (VariantKind::Int, VariantKind::Float) | (VariantKind::Float, VariantKind::Int) => {
+                VariantKind::Float
+            }
+            (VariantKind::Int, VariantKind::Decimal) | (VariantKind::Decimal, VariantKind::Int) => {
+                VariantKind::Decimal
+            }
+            (VariantKind::Date, VariantKind::DateTime)
+            | (VariantKind::DateTime, VariantKind::Date) => VariantKind::DateTime,
+            _ => VariantKind::String,
+        }
+    }
+
+    /// Converts a value to this kind when it can be done without losing information,
+    /// otherwise it is returned as it is. Strings are parsed in the format they are saved in.
+    pub fn cast(&self, value: &Variant) -> Variant {
+        let cast = match (self, value) {
+            (VariantKind::Int, Variant::String(s)) => {
+                s.trim().parse::<i64>().ok().map(Variant::Int)
+            }
+            (VariantKind::Float, Variant::String(s)) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite())
+                .map(Variant::Float),
+            (VariantKind::Bool, Variant::Int(i)) if *i == 0 || *i == 1 => {
+                Some(Variant::Bool(*i == 1))
+            }
+            (VariantKind::Bool, Variant::String(s)) => match s.trim() {
+                "true" | "1" => Some(Variant::Bool(true)),
+                "false" | "0" => Some(Variant::Bool(false)),
+                _ => None,
+            },
+            (VariantKind::Date, Variant::String(s)) => NaiveDate::parse_from_str(s, DATE_FORMAT)
+                .ok()
+                .map(Variant::Date),
+            (VariantKind::Date, Variant::DateTime(t)) if t.time() == NaiveTime::MIN => {
+                Some(Variant::Date(t.date()))
+            }
+            (VariantKind::DateTime, Variant::String(s)) => {
+                NaiveDateTime::parse_from_str(s, DATETIME_FORMAT)
+                    .ok()
+                    .map(Variant::DateTime)
+            }
+            (VariantKind::DateTime, Variant::Date(d)) => {
+                Some(Variant::DateTime(d.and_time(NaiveTime::MIN)))
+            }
+            (VariantKind::Time, Variant::String(s)) => NaiveTime::parse_from_str(s, TIME_FORMAT)
+                .ok()
+                .map(Variant::Time),
+            (VariantKind::Decimal, Variant::String(s)) => {
+                Decimal::from_str(s.trim()).ok().map(Variant::Decimal)
+            }
+            (VariantKind::Decimal, Variant::Int(i)) => Some(Variant::Decimal(Decimal::from(*i))),
+            (VariantKind::Bytes, Variant::String(s)) => from_hex(s).map(Variant::Bytes),
+            (VariantKind::String, Variant::Null | Variant::String(_) | Variant::Bytes(_)) => None,
+            (VariantKind::String, _) => Some(Variant::String(value.to_string())),
+            _ => None,
+        };
+        cast.unwrap_or_else(|| value.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub kind: VariantKind,
+    pub nullable: bool,
+    /// the type of the column in the source, e.g. `varchar` for mysql; missing when inferred
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_type: Option<String>,
+}
+
+/// The columns of a table with their types, in the order of the table.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    pub columns: Vec<ColumnSchema>,
+}
+
+impl Schema {
+    /// Guesses the schema of a table from the values of its records.
+    pub fn infer(table: &Table) -> Self {
+        let columns = table
+            .column_names()
+            .into_iter()
+            .map(|name| {
+                let mut kind = VariantKind::Null;
+                // without records nothing says that the column is not nullable
+                let mut nullable = table.records.is_empty();
+                for record in &table.records {
+                    match record.fields.get(name) {
+                        Some(Variant::Null) | None => nullable = true,
+                        Some(value) => kind = kind.merge(VariantKind::of(value)),
+                    }
+                }
+                ColumnSchema {
+                    name: name.to_string(),
+                    kind,
+                    nullable,
+                    source_type: None,
+                }
+            })
+            .collect();
+        Schema { columns }
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    /// A copy of the record with every value cast to the kind of its column.
+    pub fn cast_record(&self, record: &Record) -> Record {
+        Record {
+            fields: record
+                .fields
+                .iter()
+                .map(|(name, value)| {
+                    let value = match self.column(name) {
+                        Some(column) => column.kind.cast(value),
+                        None => value.clone(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn make_table(rows: &[&[(&str, Variant)]]) -> Table {
+        Table {
+            name: "t".to_string(),
+            records: rows
+                .iter()
+                .map(|row| Record {
+                    fields: row
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect::<IndexMap<String, Variant>>(),
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    #[test]
+    fn test_infer() {
+        let table = make_table(&[
+            &[
+                ("id", Variant::Int(1)),
+                ("amount", Variant::Int(10)),
+                ("code", Variant::Int(7)),
+                ("note", Variant::Null),
+            ],
+            &[
+                ("id", Variant::Int(2)),
+                ("amount", Variant::Float(2.5)),
+                ("code", Variant::String("A7".to_string())),
+            ],
+        ]);
+        let schema = Schema::infer(&table);
+        let kinds: Vec<(&str, VariantKind, bool)> = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.kind, c.nullable))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("id", VariantKind::Int, false),
+                ("amount", VariantKind::Float, false),
+                ("code", VariantKind::String, false),
+                ("note", VariantKind::Null, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cast() {
+        let text = |s: &str| Variant::String(s.to_string());
+        assert_eq!(VariantKind::Int.cast(&text("42")), Variant::Int(42));
+        assert_eq!(VariantKind::Int.cast(&text("4.2")), text("4.2"));
+        assert_eq!(VariantKind::Float.cast(&text("4.2")), Variant::Float(4.2));
+        assert_eq!(VariantKind::String.cast(&Variant::Int(7)), text("7"));
+        assert_eq!(VariantKind::String.cast(&Variant::Null), Variant::Null);
+        assert_eq!(
+            VariantKind::Date.cast(&text("2024-05-01")),
+            Variant::Date(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap())
+        );
+        assert_eq!(
+            VariantKind::Decimal.cast(&text("10.50")),
+            Variant::Decimal(Decimal::new(1050, 2))
+        );
+        assert_eq!(
+            VariantKind::Bytes.cast(&text("cafe")),
+            Variant::Bytes(vec![0xca, 0xfe])
+        );
+        assert_eq!(
+            VariantKind::Bool.cast(&Variant::Int(1)),
+            Variant::Bool(true)
+        );
+        assert_eq!(
+            VariantKind::Date.merge(VariantKind::DateTime),
+            VariantKind::DateTime
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let schema = Schema {
+            columns: vec![ColumnSchema {
+                name: "id".to_string(),
+                kind: VariantKind::Int,
+                nullable: false,
+                source_type: Some("long".to_string()),
+            }],
+        };
+        let serialized = serde_json::to_string(&schema).unwrap();
+        assert_eq!(
+            serialized,
+            r#"[{"name":"id","kind":"int","nullable":false,"source_type":"long"}]"#
+        );
+        assert_eq!(serde_json::from_str::<Schema>(&serialized).unwrap(), schema);
+    }
+}
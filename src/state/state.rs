@@ -1,4 +1,4 @@
-use crate::state::{Config, Table, Variant};
+use crate::state::{Config, Schema, Table, Variant};
 use mysql::Value as MysqlValue;
 use serde::de::{MapAccess, Visitor};
 use serde::ser::SerializeMap;
@@ -47,6 +47,9 @@ impl ResultsWriter for MemoryWriter {
 
 pub trait InputReader: Debug {
     fn read(&self, name: &str) -> Vec<String>;
+    fn read_bytes(&self, name: &str) -> Vec<u8> {
+        self.read(name).join("\n").into_bytes()
+    }
 }
 
 #[derive(Debug)]
@@ -57,6 +60,9 @@ impl InputReader for FileReader {
         let content = std::fs::read_to_string(path).unwrap();
         content.lines().map(|s| s.to_string()).collect()
     }
+    fn read_bytes(&self, name: &str) -> Vec<u8> {
+        std::fs::read(name).unwrap()
+    }
 }
 
 #[derive(Debug)]
@@ -76,6 +82,9 @@ impl InputReader for MemoryReader {
     }
 }
 
+/// The key of the schema inferred by `State::save` in a saved table.
+const INFERRED_SCHEMA: &str = "inferred_schema";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct State {
     pub tables: Vec<Table>,
@@ -84,6 +93,12 @@ pub struct State {
     pub results_writer: Box<dyn ResultsWriter>,
     #[serde(skip, default = "make_file_reader")]
     pub input_reader: Box<dyn InputReader>,
+    /// exit code of the process, set by the nodes that detect a failure
+    #[serde(skip)]
+    pub exit_code: i32,
+    /// when set the remaining nodes of the plan are not run
+    #[serde(skip)]
+    pub halted: bool,
 }
 
 fn make_file_writer() -> Box<dyn ResultsWriter> {
@@ -104,6 +119,8 @@ impl State {
             plan: Vec::new(),
             results_writer: results_writer.unwrap_or(Box::new(FileWriter {})),
             input_reader: input_reader.unwrap_or(Box::new(FileReader {})),
+            exit_code: 0,
+            halted: false,
         }
     }
     pub fn load_or_make(config: &Config, pipeline: &SerdeValue) -> State {
@@ -129,7 +146,19 @@ impl State {
         self.tables.iter().find(|t| t.name == table_name)
     }
     pub fn save(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let json = serde_json::to_string_pretty(self)?;
+        // dates, decimals and bytes are saved as strings, the schema tells how to read them back;
+        // an inferred one is kept apart, so that it is not taken as the source's one on load
+        let mut json = serde_json::to_value(&*self)?;
+        let saved_tables = json["tables"].as_array_mut().unwrap();
+        for (table, saved) in self.tables.iter().zip(saved_tables) {
+            if table.schema.is_none() {
+                let schema = Schema::infer(table);
+                if schema.columns.iter().any(|c| !c.kind.is_json_type()) {
+                    saved[INFERRED_SCHEMA] = serde_json::to_value(schema)?;
+                }
+            }
+        }
+        let json = serde_json::to_string_pretty(&json)?;
         self.results_writer.write(path, &[json]);
         Ok(())
     }
@@ -139,20 +168,52 @@ impl State {
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let reader = input_reader.unwrap_or(Box::new(FileReader {}));
         let json = reader.read(path).join("\n");
-        let state = serde_json::from_str(&json)?;
+        let mut json: SerdeValue = serde_json::from_str(&json)?;
+        let inferred: Vec<Option<Schema>> = match json["tables"].as_array_mut() {
+            Some(tables) => tables
+                .iter_mut()
+                .map(|t| t.as_object_mut().and_then(|t| t.remove(INFERRED_SCHEMA)))
+                .map(|schema| schema.map(serde_json::from_value).transpose())
+                .collect::<Result<_, _>>()?,
+            None => vec![],
+        };
+        let mut state: State = serde_json::from_value(json)?;
+        // only the kinds without a json type were turned into strings by save
+        for (table, inferred) in state.tables.iter_mut().zip(inferred) {
+            let Some(schema) = table.schema.as_ref().or(inferred.as_ref()) else {
+                continue;
+            };
+            for record in &mut table.records {
+                for (name, value) in record.fields.iter_mut() {
+                    if let Some(column) = schema.column(name).filter(|c| !c.kind.is_json_type()) {
+                        *value = column.kind.cast(value);
+                    }
+                }
+            }
+        }
         Ok(state)
     }
+    /// Marks the pipeline as failed, `halt` stops it before the next node.
+    pub fn fail(&mut self, halt: bool) {
+        self.exit_code = 1;
+        self.halted |= halt;
+    }
     pub fn write_file(&mut self, name: &str, rows: &[String]) {
         self.results_writer.write(name, rows);
     }
     pub fn read_file(&self, name: &str) -> Vec<String> {
         self.input_reader.read(name)
     }
+    pub fn read_file_bytes(&self, name: &str) -> Vec<u8> {
+        self.input_reader.read_bytes(name)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::state::{ColumnSchema, Record, VariantKind};
+    use indexmap::IndexMap;
     // Make sure to include serde_json in your dependencies for serialization
 
     #[test]
@@ -169,4 +230,91 @@ mod tests {
             Some(Box::new(MemoryReader::new())),
         );
     }
+
+    #[test]
+    fn test_save_and_load_typed_values() {
+        let mut fields = IndexMap::new();
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        fields.insert("id".to_string(), Variant::Int(1));
+        fields.insert("active".to_string(), Variant::Bool(true));
+        fields.insert("born".to_string(), Variant::Date(date));
+        fields.insert(
+            "amount".to_string(),
+            Variant::Decimal(rust_decimal::Decimal::new(1050, 2)),
+        );
+        fields.insert("photo".to_string(), Variant::Bytes(vec![1, 2, 254]));
+        let mut state = State::new(Some(Box::new(MemoryWriter::new())), None);
+        state.tables.push(Table {
+            name: "people".to_string(),
+            records: vec![Record { fields }],
+            columns: vec![],
+            schema: None,
+        });
+        state.save("state.json").unwrap();
+        assert!(state.tables[0].schema.is_none());
+
+        let mut reader = MemoryReader::new();
+        reader.files.insert(
+            "state.json".to_string(),
+            state
+                .results_writer
+                .test_peek("state.json")
+                .unwrap()
+                .clone(),
+        );
+        let loaded = State::load("state.json", Some(Box::new(reader))).unwrap();
+        let table = loaded.find_table("people").unwrap();
+        // the inferred schema only serves to read the values back
+        assert!(table.schema.is_none());
+        let record = &table.records[0].fields;
+        assert_eq!(record["active"], Variant::Bool(true));
+        assert_eq!(record["born"], Variant::Date(date));
+        assert_eq!(
+            record["amount"],
+            Variant::Decimal(rust_decimal::Decimal::new(1050, 2))
+        );
+        assert_eq!(record["photo"], Variant::Bytes(vec![1, 2, 254]));
+    }
+
+    #[test]
+    fn test_source_schema_survives_save_and_load() {
+        let mut fields = IndexMap::new();
+        fields.insert(
+            "amount".to_string(),
+            Variant::Decimal(rust_decimal::Decimal::new(7, 0)),
+        );
+        let schema = Schema {
+            columns: vec![ColumnSchema {
+                name: "amount".to_string(),
+                kind: VariantKind::Decimal,
+                nullable: false,
+                source_type: Some("newdecimal".to_string()),
+            }],
+        };
+        let mut state = State::new(Some(Box::new(MemoryWriter::new())), None);
+        state.tables.push(Table {
+            name: "orders".to_string(),
+            records: vec![Record { fields }],
+            columns: vec![],
+            schema: Some(schema.clone()),
+        });
+        state.save("state.json").unwrap();
+
+        let mut reader = MemoryReader::new();
+        reader.files.insert(
+            "state.json".to_string(),
+            state
+                .results_writer
+                .test_peek("state.json")
+                .unwrap()
+                .clone(),
+        );
+        let loaded = State::load("state.json", Some(Box::new(reader))).unwrap();
+        let table = loaded.find_table("orders").unwrap();
+        assert_eq!(table.schema, Some(schema));
+        assert_eq!(
+            table.records[0].fields["amount"],
+            Variant::Decimal(rust_decimal::Decimal::new(7, 0))
+        );
+    }
 }
@@ -1,48 +1,257 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
 use mysql::Value as MysqlValue;
+use postgres::types::{FromSql, Type as PostgresType};
+use postgres::Row as PostgresRow;
+use rusqlite::types::{Value as SqliteValue, ValueRef as SqliteValueRef};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Number as SerdeNumber, Value as SerdeValue};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt::Debug;
 
-#[derive(Debug)]
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+pub const TIME_FORMAT: &str = "%H:%M:%S%.f";
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// A postgres NUMERIC: a Decimal when it fits, otherwise its text form, so that NaN,
+/// the infinities and numbers beyond 28 digits are kept instead of failing the read.
+struct PostgresNumeric(Variant);
+
+impl PostgresNumeric {
+    fn text(raw: &[u8]) -> Option<String> {
+        let words: Vec<u16> = raw
+            .chunks(2)
+            .map(|w| w.try_into().ok().map(u16::from_be_bytes))
+            .collect::<Option<_>>()?;
+        let (header, digits) = words.split_at_checked(4)?;
+        let (weight, sign, scale) = (header[1] as i16 as i64, header[2], header[3] as usize);
+        let digit = |i: i64| {
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| digits.get(i))
+                .unwrap_or(&0)
+        };
+        let sign = match sign {
+            0x0000 => "",
+            0x4000 => "-",
+            0xC000 => return Some("NaN".to_string()),
+            0xD000 => return Some("Infinity".to_string()),
+            0xF000 => return Some("-Infinity".to_string()),
+            _ => return None,
+        };
+        let mut text = sign.to_string();
+        if weight < 0 {
+            text += "0";
+        } else {
+            text += &digit(0).to_string();
+            for i in 1..=weight {
+                text += &format!("{:04}", digit(i));
+            }
+        }
+        if scale > 0 {
+            let fraction: String = (weight + 1..)
+                .take(scale.div_ceil(4))
+                .map(|i| format!("{:04}", digit(i)))
+                .collect();
+            text += ".";
+            text += &fraction[..scale];
+        }
+        Some(text)
+    }
+}
+
+impl<'a> FromSql<'a> for PostgresNumeric {
+    fn from_sql(
+        _: &PostgresType,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let text = PostgresNumeric::text(raw).ok_or("malformed numeric")?;
+        Ok(PostgresNumeric(match Decimal::from_str_exact(&text) {
+            Ok(d) => Variant::Decimal(d),
+            Err(_) => Variant::String(text),
+        }))
+    }
+
+    fn accepts(ty: &PostgresType) -> bool {
+        *ty == PostgresType::NUMERIC
+    }
+}
+
+/// A value of a field. Json has no type for dates, times, decimals and bytes: they are
+/// written as strings (bytes in hex) and read back as such unless a schema says otherwise.
+#[derive(Debug, Clone)]
 pub enum Variant {
     Null,
     String(String),
     Float(f64),
     Int(i64),
+    Bool(bool),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Time(NaiveTime),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
 }
 
 impl Variant {
     pub fn from_serde_value(value: &SerdeValue) -> Variant {
         match value {
             SerdeValue::Null => Variant::Null,
+            SerdeValue::Bool(b) => Variant::Bool(*b),
             SerdeValue::String(s) => Variant::String(s.clone()),
-            SerdeValue::Number(n) => {
-                if n.is_f64() {
-                    Variant::Float(n.as_f64().unwrap())
-                } else {
-                    Variant::Int(n.as_i64().unwrap())
-                }
-            }
-            _ => {
-                println!("value: {:?}", value);
-                panic!("Unsupported value type");
-            }
+            SerdeValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => Variant::Int(i),
+                // integers beyond i64 stay exact as decimals
+                (None, Some(u)) => Variant::Decimal(Decimal::from(u)),
+                _ => Variant::Float(n.as_f64().unwrap()),
+            },
+            // nested values are kept as their json text
+            SerdeValue::Array(_) | SerdeValue::Object(_) => Variant::String(value.to_string()),
         }
     }
 
     pub fn from_mysql_value(value: MysqlValue) -> Variant {
         match value {
             MysqlValue::NULL => Variant::Null,
-            MysqlValue::Bytes(bytes) => {
-                let s = String::from_utf8(bytes).unwrap();
-                Variant::String(s)
-            }
+            MysqlValue::Bytes(bytes) => match String::from_utf8(bytes) {
+                Ok(s) => Variant::String(s),
+                Err(e) => Variant::Bytes(e.into_bytes()),
+            },
             MysqlValue::Int(i) => Variant::Int(i),
-            MysqlValue::UInt(u) => Variant::Int(u as i64),
+            // unsigned bigints beyond i64 stay exact as decimals
+            MysqlValue::UInt(u) => {
+                i64::try_from(u).map_or_else(|_| Variant::Decimal(Decimal::from(u)), Variant::Int)
+            }
             MysqlValue::Float(f) => Variant::Float(f as f64),
             MysqlValue::Double(d) => Variant::Float(d),
-            _ => panic!("Unsupported value type"),
+            // dates and datetimes are the same value in the protocol, the schema tells them apart
+            MysqlValue::Date(year, month, day, hour, minute, second, micros) => {
+                NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                    .and_then(|d| {
+                        d.and_hms_micro_opt(hour as u32, minute as u32, second as u32, micros)
+                    })
+                    // zero dates like 0000-00-00 have no representation
+                    .map_or(Variant::Null, Variant::DateTime)
+            }
+            MysqlValue::Time(negative, days, hours, minutes, seconds, micros) => {
+                match NaiveTime::from_hms_micro_opt(
+                    hours as u32,
+                    minutes as u32,
+                    seconds as u32,
+                    micros,
+                ) {
+                    Some(time) if !negative && days == 0 => Variant::Time(time),
+                    // a duration rather than a time of the day
+                    _ => Variant::String(format!(
+                        "{}{}:{:02}:{:02}{}",
+                        if negative { "-" } else { "" },
+                        days * 24 + hours as u32,
+                        minutes,
+                        seconds,
+                        if micros > 0 {
+                            format!(".{:06}", micros)
+                        } else {
+                            "".to_string()
+                        }
+                    )),
+                }
+            }
+        }
+    }
+
+    pub fn from_postgres_column(row: &PostgresRow, index: usize) -> Result<Variant, String> {
+        fn get<'a, T: FromSql<'a>>(
+            row: &'a PostgresRow,
+            index: usize,
+            to_variant: impl Fn(T) -> Variant,
+        ) -> Result<Variant, String> {
+            let column = &row.columns()[index];
+            match row.try_get::<_, Option<T>>(index) {
+                Ok(Some(value)) => Ok(to_variant(value)),
+                Ok(None) => Ok(Variant::Null),
+                Err(e) => Err(format!(
+                    "cannot read column {} of type {}: {}",
+                    column.name(),
+                    column.type_(),
+                    e
+                )),
+            }
+        }
+
+        let column = &row.columns()[index];
+        match *column.type_() {
+            PostgresType::BOOL => get(row, index, Variant::Bool),
+            PostgresType::INT2 => get(row, index, |i: i16| Variant::Int(i as i64)),
+            PostgresType::INT4 => get(row, index, |i: i32| Variant::Int(i as i64)),
+            PostgresType::INT8 => get(row, index, Variant::Int),
+            PostgresType::OID => get(row, index, |i: u32| Variant::Int(i as i64)),
+            PostgresType::FLOAT4 => get(row, index, |f: f32| Variant::Float(f as f64)),
+            PostgresType::FLOAT8 => get(row, index, Variant::Float),
+            PostgresType::NUMERIC => get(row, index, |n: PostgresNumeric| n.0),
+            PostgresType::TEXT
+            | PostgresType::VARCHAR
+            | PostgresType::BPCHAR
+            | PostgresType::NAME
+            | PostgresType::UNKNOWN => get(row, index, Variant::String),
+            PostgresType::TIMESTAMP => get(row, index, Variant::DateTime),
+            PostgresType::TIMESTAMPTZ => get(row, index, |t: DateTime<Utc>| {
+                Variant::DateTime(t.naive_utc())
+            }),
+            PostgresType::DATE => get(row, index, Variant::Date),
+            PostgresType::TIME => get(row, index, Variant::Time),
+            PostgresType::BYTEA => get(row, index, Variant::Bytes),
+            PostgresType::JSON | PostgresType::JSONB => {
+                get(row, index, |j: SerdeValue| Variant::String(j.to_string()))
+            }
+            PostgresType::UUID => get(row, index, |u: uuid::Uuid| Variant::String(u.to_string())),
+            _ => Err(format!(
+                "column {} has unsupported postgres type {}",
+                column.name(),
+                column.type_()
+            )),
+        }
+    }
+
+    pub fn from_sqlite_value(value: SqliteValueRef) -> Variant {
+        match value {
+            SqliteValueRef::Null => Variant::Null,
+            SqliteValueRef::Integer(i) => Variant::Int(i),
+            SqliteValueRef::Real(f) => Variant::Float(f),
+            SqliteValueRef::Text(bytes) => {
+                Variant::String(String::from_utf8_lossy(bytes).to_string())
+            }
+            SqliteValueRef::Blob(bytes) => Variant::Bytes(bytes.to_vec()),
+        }
+    }
+
+    pub fn to_sqlite_value(&self) -> SqliteValue {
+        match self {
+            Variant::Null => SqliteValue::Null,
+            Variant::String(s) => SqliteValue::Text(s.clone()),
+            Variant::Int(i) => SqliteValue::Integer(*i),
+            Variant::Float(f) => SqliteValue::Real(*f),
+            Variant::Bool(b) => SqliteValue::Integer(*b as i64),
+            // sqlite has no date or decimal types, text keeps them readable and exact
+            Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_) | Variant::Decimal(_) => {
+                SqliteValue::Text(self.to_string())
+            }
+            Variant::Bytes(b) => SqliteValue::Blob(b.clone()),
         }
     }
 
@@ -52,6 +261,29 @@ impl Variant {
             Variant::String(s) => MysqlValue::Bytes(s.as_bytes().to_vec()),
             Variant::Int(i) => MysqlValue::Int(*i),
             Variant::Float(f) => MysqlValue::Double(*f),
+            Variant::Bool(b) => MysqlValue::Int(*b as i64),
+            Variant::Date(d) => {
+                MysqlValue::Date(d.year() as u16, d.month() as u8, d.day() as u8, 0, 0, 0, 0)
+            }
+            Variant::DateTime(t) => MysqlValue::Date(
+                t.year() as u16,
+                t.month() as u8,
+                t.day() as u8,
+                t.hour() as u8,
+                t.minute() as u8,
+                t.second() as u8,
+                t.nanosecond() / 1000,
+            ),
+            Variant::Time(t) => MysqlValue::Time(
+                false,
+                0,
+                t.hour() as u8,
+                t.minute() as u8,
+                t.second() as u8,
+                t.nanosecond() / 1000,
+            ),
+            Variant::Decimal(d) => MysqlValue::Bytes(d.to_string().into_bytes()),
+            Variant::Bytes(b) => MysqlValue::Bytes(b.clone()),
         }
     }
 
@@ -61,15 +293,83 @@ impl Variant {
             Variant::String(s) => SerdeValue::String(s.clone()),
             Variant::Int(i) => SerdeValue::Number((*i).into()),
             Variant::Float(f) => SerdeValue::Number(SerdeNumber::from_f64(*f).unwrap()),
+            Variant::Bool(b) => SerdeValue::Bool(*b),
+            Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_) | Variant::Decimal(_) => {
+                SerdeValue::String(self.to_string())
+            }
+            Variant::Bytes(b) => SerdeValue::String(to_hex(b)),
         }
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         match self {
             Variant::Null => "NULL".to_string(),
-            Variant::String(s) => format!("{}", s),
+            Variant::String(s) => s.clone(),
             Variant::Int(i) => i.to_string(),
             Variant::Float(f) => f.to_string(),
+            Variant::Bool(b) => b.to_string(),
+            Variant::Date(d) => d.format(DATE_FORMAT).to_string(),
+            Variant::DateTime(t) => t.format(DATETIME_FORMAT).to_string(),
+            Variant::Time(t) => t.format(TIME_FORMAT).to_string(),
+            Variant::Decimal(d) => d.to_string(),
+            Variant::Bytes(b) => to_hex(b),
+        }
+    }
+}
+
+impl Variant {
+    // the position of the variant in the order across types
+    fn rank(&self) -> u8 {
+        match self {
+            Variant::Null => 0,
+            Variant::Bool(_) | Variant::Int(_) | Variant::Float(_) | Variant::Decimal(_) => 1,
+            Variant::Date(_) | Variant::DateTime(_) => 2,
+            Variant::Time(_) => 3,
+            Variant::String(_) => 4,
+            Variant::Bytes(_) => 5,
+        }
+    }
+
+    /// A total order across variants: NULL first, then numbers (and booleans, as 0 and 1)
+    /// compared by value, dates, times, strings and bytes.
+    pub fn compare(&self, other: &Variant) -> Ordering {
+        match (self, other) {
+            (Variant::Int(i1), Variant::Int(i2)) => i1.cmp(i2),
+            (Variant::Date(d1), Variant::Date(d2)) => d1.cmp(d2),
+            (Variant::Date(d), Variant::DateTime(t)) => d.and_time(NaiveTime::MIN).cmp(t),
+            (Variant::DateTime(t), Variant::Date(d)) => t.cmp(&d.and_time(NaiveTime::MIN)),
+            (Variant::DateTime(t1), Variant::DateTime(t2)) => t1.cmp(t2),
+            (Variant::Time(t1), Variant::Time(t2)) => t1.cmp(t2),
+            (Variant::String(s1), Variant::String(s2)) => s1.cmp(s2),
+            (Variant::Bytes(b1), Variant::Bytes(b2)) => b1.cmp(b2),
+            // decimals are compared exactly unless the other side is a float
+            (v1, v2) if v1.rank() == 1 && v2.rank() == 1 => {
+                match (v1.as_decimal(), v2.as_decimal()) {
+                    (Some(d1), Some(d2)) => d1.cmp(&d2),
+                    _ => v1.as_f64().unwrap().total_cmp(&v2.as_f64().unwrap()),
+                }
+            }
+            (v1, v2) => v1.rank().cmp(&v2.rank()),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Variant::Int(i) => Some(*i as f64),
+            Variant::Float(f) => Some(*f),
+            Variant::Bool(b) => Some(*b as i64 as f64),
+            Variant::Decimal(d) => d.to_f64(),
+            _ => None,
+        }
+    }
+
+    fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Variant::Int(i) => Some(Decimal::from(*i)),
+            Variant::Bool(b) => Some(Decimal::from(*b as i64)),
+            Variant::Decimal(d) => Some(*d),
+            _ => None,
         }
     }
 }
@@ -89,6 +389,12 @@ impl PartialEq for Variant {
             (Variant::String(s1), Variant::String(s2)) => s1 == s2,
             (Variant::Int(i1), Variant::Int(i2)) => i1 == i2,
             (Variant::Float(f1), Variant::Float(f2)) => f1 == f2,
+            (Variant::Bool(b1), Variant::Bool(b2)) => b1 == b2,
+            (Variant::Date(d1), Variant::Date(d2)) => d1 == d2,
+            (Variant::DateTime(t1), Variant::DateTime(t2)) => t1 == t2,
+            (Variant::Time(t1), Variant::Time(t2)) => t1 == t2,
+            (Variant::Decimal(d1), Variant::Decimal(d2)) => d1 == d2,
+            (Variant::Bytes(b1), Variant::Bytes(b2)) => b1 == b2,
             _ => false,
         }
     }
@@ -117,6 +423,7 @@ impl<'de> Deserialize<'de> for Variant {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::state::VariantKind;
 
     #[test]
     fn test_serialize_string() {
@@ -159,4 +466,165 @@ mod tests {
         let v: Variant = serde_json::from_str(s).unwrap();
         assert_eq!(v, Variant::Float(123.45));
     }
+
+    #[test]
+    fn test_compare_numbers_by_value() {
+        assert_eq!(
+            Variant::Int(1).compare(&Variant::Float(1.0)),
+            Ordering::Equal
+        );
+        assert_eq!(Variant::Int(2).compare(&Variant::Int(10)), Ordering::Less);
+        assert_eq!(
+            Variant::Float(2.5).compare(&Variant::Int(2)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Variant::Null.compare(&Variant::String("a".to_string())),
+            Ordering::Less
+        );
+        assert_eq!(
+            Variant::String("1".to_string()).compare(&Variant::Int(1)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_deserialize_big_ints_and_nested_values() {
+        let v: Variant = serde_json::from_str("18446744073709551615").unwrap();
+        assert_eq!(v, Variant::Decimal(Decimal::from(u64::MAX)));
+        let v: Variant = serde_json::from_str("-9223372036854775808").unwrap();
+        assert_eq!(v, Variant::Int(i64::MIN));
+        let v: Variant = serde_json::from_str(r#"[1, {"a": null}]"#).unwrap();
+        assert_eq!(v, Variant::String(r#"[1,{"a":null}]"#.to_string()));
+    }
+
+    #[test]
+    fn test_serde_bool_and_typed_strings() {
+        let v: Variant = serde_json::from_str("true").unwrap();
+        assert_eq!(v, Variant::Bool(true));
+        assert_eq!(serde_json::to_string(&v).unwrap(), "true");
+
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let values = [
+            (Variant::Date(date), "\"2024-02-29\""),
+            (
+                Variant::DateTime(date.and_hms_micro_opt(13, 5, 0, 250000).unwrap()),
+                "\"2024-02-29 13:05:00.250\"",
+            ),
+            (
+                Variant::Time(NaiveTime::from_hms_opt(8, 30, 0).unwrap()),
+                "\"08:30:00\"",
+            ),
+            (Variant::Decimal(Decimal::new(1250, 2)), "\"12.50\""),
+            (Variant::Bytes(vec![0, 255, 16]), "\"00ff10\""),
+        ];
+        for (value, json) in values {
+            assert_eq!(serde_json::to_string(&value).unwrap(), json);
+        }
+        assert_eq!(from_hex("00ff10"), Some(vec![0, 255, 16]));
+        assert_eq!(from_hex("0g"), None);
+    }
+
+    #[test]
+    fn test_mysql_values() {
+        assert_eq!(
+            Variant::from_mysql_value(MysqlValue::Bytes(vec![0xff, 0x00])),
+            Variant::Bytes(vec![0xff, 0x00])
+        );
+        let datetime = NaiveDate::from_ymd_opt(2024, 1, 2)
+            .unwrap()
+            .and_hms_micro_opt(3, 4, 5, 6)
+            .unwrap();
+        let time = NaiveTime::from_hms_opt(23, 59, 1).unwrap();
+        let decimal = Decimal::new(-100001, 3);
+        for value in [
+            Variant::DateTime(datetime),
+            Variant::Time(time),
+            Variant::Bool(true),
+        ] {
+            let round_trip = Variant::from_mysql_value(value.to_mysql_value());
+            assert_eq!(round_trip.compare(&value), Ordering::Equal);
+        }
+        // decimals are sent as text, the schema of the column reads them back
+        let round_trip = Variant::from_mysql_value(Variant::Decimal(decimal).to_mysql_value());
+        assert_eq!(
+            VariantKind::Decimal.cast(&round_trip),
+            Variant::Decimal(decimal)
+        );
+        assert_eq!(
+            Variant::from_mysql_value(MysqlValue::UInt(u64::MAX)),
+            Variant::Decimal(Decimal::from(u64::MAX))
+        );
+        assert_eq!(
+            Variant::from_mysql_value(MysqlValue::UInt(7)),
+            Variant::Int(7)
+        );
+        assert_eq!(
+            Variant::from_mysql_value(MysqlValue::Date(0, 0, 0, 0, 0, 0, 0)),
+            Variant::Null
+        );
+        assert_eq!(
+            Variant::from_mysql_value(MysqlValue::Time(true, 1, 2, 3, 4, 0)),
+            Variant::String("-26:03:04".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compare_new_variants() {
+        let decimal = Variant::Decimal(Decimal::new(15, 1));
+        assert_eq!(decimal.compare(&Variant::Int(1)), Ordering::Greater);
+        assert_eq!(decimal.compare(&Variant::Float(1.5)), Ordering::Equal);
+        assert_eq!(
+            Variant::Bool(true).compare(&Variant::Int(1)),
+            Ordering::Equal
+        );
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(
+            Variant::Date(date).compare(&Variant::DateTime(date.and_hms_opt(0, 0, 1).unwrap())),
+            Ordering::Less
+        );
+        assert_eq!(
+            Variant::Date(date).compare(&Variant::String("2024".to_string())),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_postgres_numeric() {
+        fn numeric(weight: i16, sign: u16, scale: u16, digits: &[u16]) -> Variant {
+            let mut raw = vec![];
+            for word in [digits.len() as u16, weight as u16, sign, scale]
+                .iter()
+                .chain(digits)
+            {
+                raw.extend_from_slice(&word.to_be_bytes());
+            }
+            PostgresNumeric::from_sql(&PostgresType::NUMERIC, &raw)
+                .unwrap()
+                .0
+        }
+
+        assert_eq!(
+            numeric(0, 0x4000, 1, &[12, 5000]),
+            Variant::Decimal(Decimal::new(-125, 1))
+        );
+        assert_eq!(
+            numeric(0, 0xC000, 0, &[]),
+            Variant::String("NaN".to_string())
+        );
+        assert_eq!(
+            numeric(0, 0xF000, 0, &[]),
+            Variant::String("-Infinity".to_string())
+        );
+        // 10^40 and 10^-30 do not fit in a Decimal
+        assert_eq!(
+            numeric(10, 0x0000, 0, &[1]),
+            Variant::String(format!("1{}", "0".repeat(40)))
+        );
+        assert_eq!(
+            numeric(-8, 0x4000, 30, &[100]),
+            Variant::String(format!("-0.{}1", "0".repeat(29)))
+        );
+        assert!(PostgresNumeric::from_sql(&PostgresType::NUMERIC, &[0, 1]).is_err());
+    }
 }
@@ -0,0 +1,367 @@
+use crate::register_process;
+use crate::state::{
+    read_config_field, read_fields_list, Factory, Process, Record, State, Table, Variant,
+};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AggregateFunction {
+    /// counts the records when the field is "*", the non-null values otherwise
+    Count,
+    CountDistinct,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    First,
+    Last,
+    Concat(String),
+}
+
+/// An aggregated field, configured as `"name": {"<function>": "<field>"}`; `concat` also
+/// accepts a `separator`. Null values are ignored, as in sql.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Aggregate {
+    name: String,
+    function: AggregateFunction,
+    field: String,
+}
+
+impl Aggregate {
+    fn parse(name: &str, config: &Value) -> Self {
+        let config = config
+            .as_object()
+            .unwrap_or_else(|| panic!("aggregate {} must be an object", name));
+        let (function, field) = config
+            .iter()
+            .find(|(k, _)| k.as_str() != "separator")
+            .unwrap_or_else(|| panic!("aggregate {} has no function", name));
+        let function = match function.as_str() {
+            "count" => AggregateFunction::Count,
+            "count-distinct" => AggregateFunction::CountDistinct,
+            "sum" => AggregateFunction::Sum,
+            "avg" => AggregateFunction::Avg,
+            "min" => AggregateFunction::Min,
+            "max" => AggregateFunction::Max,
+            "first" => AggregateFunction::First,
+            "last" => AggregateFunction::Last,
+            "concat" => AggregateFunction::Concat(
+                config
+                    .get("separator")
+                    .and_then(|s| s.as_str())
+                    .unwrap_or(",")
+                    .to_string(),
+            ),
+            _ => panic!("Unknown aggregate function {}", function),
+        };
+        Aggregate {
+            name: name.to_string(),
+            function,
+            field: field
+                .as_str()
+                .unwrap_or_else(|| panic!("aggregate {} needs a field name", name))
+                .to_string(),
+        }
+    }
+
+    fn number(&self, value: &Variant) -> Variant {
+        match value {
+            Variant::Int(_) | Variant::Float(_) | Variant::Decimal(_) => value.clone(),
+            _ => panic!(
+                "cannot aggregate {} with a non numeric value: {}",
+                self.name, value
+            ),
+        }
+    }
+
+    fn sum(&self, values: &[&Variant]) -> Variant {
+        let decimal_sum = |d1: Decimal, d2: Decimal| match d1.checked_add(d2) {
+            Some(d) => Variant::Decimal(d),
+            None => Variant::Float(d1.to_f64().unwrap() + d2.to_f64().unwrap()),
+        };
+        values
+            .iter()
+            .fold(Variant::Null, |acc, v| match (acc, self.number(v)) {
+                (Variant::Null, v) => v,
+                (Variant::Int(i1), Variant::Int(i2)) => match i1.checked_add(i2) {
+                    Some(i) => Variant::Int(i),
+                    None => Variant::Float(i1 as f64 + i2 as f64),
+                },
+                // decimals stay exact unless summed with floats or beyond their range
+                (Variant::Decimal(d), Variant::Int(i)) | (Variant::Int(i), Variant::Decimal(d)) => {
+                    decimal_sum(d, Decimal::from(i))
+                }
+                (Variant::Decimal(d1), Variant::Decimal(d2)) => decimal_sum(d1, d2),
+                (acc, v) => Variant::Float(acc.as_f64().unwrap() + v.as_f64().unwrap()),
+            })
+    }
+
+    pub fn compute(&self, records: &[&Record]) -> Variant {
+        if self.function == AggregateFunction::Count && self.field == "*" {
+            return Variant::Int(records.len() as i64);
+        }
+        let values: Vec<&Variant> = records
+            .iter()
+            .filter_map(|r| r.fields.get(&self.field))
+            .filter(|v| **v != Variant::Null)
+            .collect();
+        match &self.function {
+            AggregateFunction::Count => Variant::Int(values.len() as i64),
+            AggregateFunction::CountDistinct => Variant::Int(
+                values
+                    .iter()
+                    .sorted_by(|v1, v2| v1.compare(v2))
+                    .dedup_by(|v1, v2| v1.compare(v2) == Ordering::Equal)
+                    .count() as i64,
+            ),
+            AggregateFunction::Sum => self.sum(&values),
+            AggregateFunction::Avg => match self.sum(&values).as_f64() {
+                Some(sum) => Variant::Float(sum / values.len() as f64),
+                None => Variant::Null,
+            },
+            AggregateFunction::Min => values
+                .iter()
+                .min_by(|v1, v2| v1.compare(v2))
+                .map_or(Variant::Null, |v| (*v).clone()),
+            AggregateFunction::Max => values
+                .iter()
+                .max_by(|v1, v2| v1.compare(v2))
+                .map_or(Variant::Null, |v| (*v).clone()),
+            AggregateFunction::First => values.first().map_or(Variant::Null, |v| (*v).clone()),
+            AggregateFunction::Last => values.last().map_or(Variant::Null, |v| (*v).clone()),
+            AggregateFunction::Concat(separator) => {
+                if values.is_empty() {
+                    Variant::Null
+                } else {
+                    Variant::String(values.iter().map(|v| Variant::to_string(v)).join(separator))
+                }
+            }
+        }
+    }
+}
+
+/// Groups the records of `input` by the `group_by` fields, one record for every group with
+/// the group fields and the aggregates. Without `group_by` the whole table is a single group.
+pub struct TransformAggregate {
+    node_name: String,
+    input: String,
+    group_by: Vec<String>,
+    aggregates: Vec<Aggregate>,
+}
+
+impl TransformAggregate {
+    fn group_key<'a>(&self, record: &'a Record) -> Vec<&'a Variant> {
+        self.group_by
+            .iter()
+            .map(|f| record.fields.get(f).unwrap_or(&Variant::Null))
+            .collect()
+    }
+
+    pub fn aggregate(&self, table: &Table) -> Table {
+        let compare = |r1: &&Record, r2: &&Record| {
+            self.group_key(r1)
+                .iter()
+                .zip(self.group_key(r2).iter())
+                .map(|(v1, v2)| v1.compare(v2))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        };
+        let sorted: Vec<&Record> = table.records.iter().sorted_by(compare).collect();
+        let mut groups: Vec<&[&Record]> = sorted
+            .chunk_by(|r1, r2| compare(r1, r2) == Ordering::Equal)
+            .collect();
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push(&[]);
+        }
+        let records = groups
+            .into_iter()
+            .map(|group| {
+                let mut fields: IndexMap<String, Variant> = IndexMap::new();
+                if let Some(first) = group.first() {
+                    for (name, value) in self.group_by.iter().zip(self.group_key(first)) {
+                        fields.insert(name.clone(), value.clone());
+                    }
+                }
+                for aggregate in &self.aggregates {
+                    fields.insert(aggregate.name.clone(), aggregate.compute(group));
+                }
+                Record { fields }
+            })
+            .collect();
+        Table {
+            name: self.node_name.clone(),
+            records,
+            columns: self
+                .group_by
+                .iter()
+                .chain(self.aggregates.iter().map(|a| &a.name))
+                .cloned()
+                .collect(),
+            schema: None,
+        }
+    }
+}
+
+impl Process for TransformAggregate {
+    register_process!(transform::aggregate);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let aggregates = config
+            .get("aggregates")
+            .and_then(|a| a.as_object())
+            .expect("aggregate needs an aggregates object")
+            .iter()
+            .map(|(name, aggregate)| Aggregate::parse(name, aggregate))
+            .collect();
+        TransformAggregate {
+            node_name,
+            input: read_config_field(&config, "input"),
+            group_by: read_fields_list(&config, "group_by"),
+            aggregates,
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        let aggregated = self.aggregate(table);
+        state.tables.push(aggregated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_orders() -> Table {
+        let rows = [
+            ("books", Variant::Int(10), "Ada"),
+            ("pens", Variant::Float(1.5), "Alan"),
+            ("books", Variant::Int(5), "Alan"),
+            ("books", Variant::Null, "Ada"),
+            ("pens", Variant::Int(2), "Grace"),
+        ];
+        Table {
+            name: "orders".to_string(),
+            records: rows
+                .into_iter()
+                .map(|(category, amount, customer)| {
+                    let mut fields = IndexMap::new();
+                    fields.insert(
+                        "category".to_string(),
+                        Variant::String(category.to_string()),
+                    );
+                    fields.insert("amount".to_string(), amount);
+                    fields.insert(
+                        "customer".to_string(),
+                        Variant::String(customer.to_string()),
+                    );
+                    Record { fields }
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn make_aggregate(config: &str) -> TransformAggregate {
+        let config: Value = serde_json::from_str(config).unwrap();
+        TransformAggregate::from_config("summary".to_string(), config.as_object().unwrap().clone())
+    }
+
+    #[test]
+    fn test_group_by_category() {
+        let aggregate = make_aggregate(
+            r#"{"input":"orders","group_by":"category","aggregates":{
+                "rows":{"count":"*"},
+                "amounts":{"count":"amount"},
+                "customers":{"count-distinct":"customer"},
+                "total":{"sum":"amount"},
+                "average":{"avg":"amount"},
+                "smallest":{"min":"amount"},
+                "largest":{"max":"amount"},
+                "first_customer":{"first":"customer"},
+                "last_customer":{"last":"customer"},
+                "names":{"concat":"customer","separator":"|"}
+            }}"#,
+        );
+        let summary = aggregate.aggregate(&make_orders());
+        assert_eq!(summary.name, "summary");
+        assert_eq!(summary.records.len(), 2);
+        let books = &summary.records[0].fields;
+        assert_eq!(books["category"], Variant::String("books".to_string()));
+        assert_eq!(books["rows"], Variant::Int(3));
+        assert_eq!(books["amounts"], Variant::Int(2));
+        assert_eq!(books["customers"], Variant::Int(2));
+        assert_eq!(books["total"], Variant::Int(15));
+        assert_eq!(books["average"], Variant::Float(7.5));
+        assert_eq!(books["smallest"], Variant::Int(5));
+        assert_eq!(books["largest"], Variant::Int(10));
+        assert_eq!(books["first_customer"], Variant::String("Ada".to_string()));
+        assert_eq!(books["last_customer"], Variant::String("Ada".to_string()));
+        assert_eq!(books["names"], Variant::String("Ada|Alan|Ada".to_string()));
+        let pens = &summary.records[1].fields;
+        assert_eq!(pens["total"], Variant::Float(3.5));
+        assert_eq!(pens["largest"], Variant::Int(2));
+    }
+
+    #[test]
+    fn test_whole_table_and_empty_table() {
+        let aggregate = make_aggregate(
+            r#"{"input":"orders","aggregates":{"rows":{"count":"*"},"total":{"sum":"amount"}}}"#,
+        );
+        let summary = aggregate.aggregate(&make_orders());
+        assert_eq!(summary.records.len(), 1);
+        assert_eq!(summary.records[0].fields["rows"], Variant::Int(5));
+        assert_eq!(summary.records[0].fields["total"], Variant::Float(18.5));
+
+        let empty = Table {
+            name: "orders".to_string(),
+            records: vec![],
+            columns: vec![],
+            schema: None,
+        };
+        let summary = aggregate.aggregate(&empty);
+        assert_eq!(summary.records.len(), 1);
+        assert_eq!(summary.records[0].fields["rows"], Variant::Int(0));
+        assert_eq!(summary.records[0].fields["total"], Variant::Null);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot aggregate total with a non numeric value")]
+    fn test_sum_of_strings() {
+        make_aggregate(r#"{"input":"orders","aggregates":{"total":{"sum":"customer"}}}"#)
+            .aggregate(&make_orders());
+    }
+
+    #[test]
+    fn test_sum_of_decimals() {
+        let mut orders = make_orders();
+        orders.records[1]
+            .fields
+            .insert("amount".to_string(), Variant::Decimal(Decimal::new(15, 1)));
+        orders.records[4]
+            .fields
+            .insert("amount".to_string(), Variant::Decimal(Decimal::new(2, 2)));
+        let aggregate = make_aggregate(
+            r#"{"input":"orders","group_by":"category","aggregates":{"total":{"sum":"amount"}}}"#,
+        );
+        let summary = aggregate.aggregate(&orders);
+        assert_eq!(
+            summary.records[1].fields["total"],
+            Variant::Decimal(Decimal::new(152, 2))
+        );
+
+        // beyond the range of decimals the sum goes on as a float
+        orders.records[4]
+            .fields
+            .insert("amount".to_string(), Variant::Decimal(Decimal::MAX));
+        let summary = aggregate.aggregate(&orders);
+        assert_eq!(
+            summary.records[1].fields["total"],
+            Variant::Float(Decimal::MAX.to_f64().unwrap() + 1.5)
+        );
+    }
+}
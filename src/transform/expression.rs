@@ -0,0 +1,633 @@
+use crate::state::{Record, Variant, VariantKind};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+
+/*
+Expressions over the fields of a record, for example:
+
+    age >= 18 and (lower(country) == 'it' or email is not null)
+
+- literals: numbers, 'strings' or "strings", true, false, null
+- fields: bare names, or `quoted names` for names with spaces or keywords
+- comparisons: == (or =), !=, <, <=, >, >=
+- boolean logic: and, or, not (or &&, ||, !)
+- null checks: x is null, x is not null
+- arithmetic: +, -, *, /
+- functions: lower, upper, trim, length, contains, starts_with, ends_with, matches, coalesce, concat
+
+Booleans are represented as Int(1) and Int(0), which compare equal to the Bool values read from
+the sources. Missing fields are null; null, false, 0 and the empty string are false, everything
+else is true.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Variant),
+    Str(String),
+    Ident(String),
+    Field(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 16] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "=", "!", "+", "-", "*", "/", "(", ")",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Symbol(","));
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(match number.parse::<i64>() {
+                Ok(n) => Variant::Int(n),
+                Err(_) => Variant::Float(
+                    number
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid number {}", number))?,
+                ),
+            }));
+        } else if c == '\'' || c == '"' || c == '`' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("unterminated {}", c)),
+                    Some('\\') if i + 1 < chars.len() => {
+                        value.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(q) if *q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(other) => {
+                        value.push(*other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '`' {
+                Token::Field(value)
+            } else {
+                Token::Str(value)
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(*s))
+                .ok_or_else(|| format!("unexpected character {}", c))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Lower,
+    Upper,
+    Trim,
+    Length,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Coalesce,
+    Concat,
+}
+
+impl Function {
+    // the function with its number of arguments, None for variadic functions
+    fn parse(name: &str) -> Option<(Function, Option<usize>)> {
+        match name {
+            "lower" => Some((Function::Lower, Some(1))),
+            "upper" => Some((Function::Upper, Some(1))),
+            "trim" => Some((Function::Trim, Some(1))),
+            "length" => Some((Function::Length, Some(1))),
+            "contains" => Some((Function::Contains, Some(2))),
+            "starts_with" => Some((Function::StartsWith, Some(2))),
+            "ends_with" => Some((Function::EndsWith, Some(2))),
+            "coalesce" => Some((Function::Coalesce, None)),
+            "concat" => Some((Function::Concat, None)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Literal(Variant),
+    Field(String),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(CompareOp, Box<Node>, Box<Node>),
+    Arithmetic(ArithmeticOp, Box<Node>, Box<Node>),
+    Negate(Box<Node>),
+    IsNull(Box<Node>, bool),
+    Call(Function, Vec<Node>),
+    Matches(Box<Node>, Regex),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept_symbol(&mut self, symbols: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Symbol(s)) if symbols.contains(s) => {
+                let s = *s;
+                self.position += 1;
+                Some(s)
+            }
+            _ => None,
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(i)) if i == keyword => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        self.accept_symbol(&[symbol])
+            .map(|_| ())
+            .ok_or_else(|| format!("expected {} but found {:?}", symbol, self.peek()))
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.accept_keyword("or") || self.accept_symbol(&["||"]).is_some() {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.accept_keyword("and") || self.accept_symbol(&["&&"]).is_some() {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.accept_keyword("not") || self.accept_symbol(&["!"]).is_some() {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let node = self.sum()?;
+        if self.accept_keyword("is") {
+            let negated = self.accept_keyword("not");
+            if !self.accept_keyword("null") {
+                return Err(format!("expected null but found {:?}", self.peek()));
+            }
+            return Ok(Node::IsNull(Box::new(node), negated));
+        }
+        let op = match self.accept_symbol(&["==", "=", "!=", "<", "<=", ">", ">="]) {
+            Some("==") | Some("=") => CompareOp::Eq,
+            Some("!=") => CompareOp::Ne,
+            Some("<") => CompareOp::Lt,
+            Some("<=") => CompareOp::Le,
+            Some(">") => CompareOp::Gt,
+            Some(">=") => CompareOp::Ge,
+            _ => return Ok(node),
+        };
+        Ok(Node::Compare(op, Box::new(node), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(symbol) = self.accept_symbol(&["+", "-"]) {
+            let op = if symbol == "+" {
+                ArithmeticOp::Add
+            } else {
+                ArithmeticOp::Sub
+            };
+            node = Node::Arithmetic(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(symbol) = self.accept_symbol(&["*", "/"]) {
+            let op = if symbol == "*" {
+                ArithmeticOp::Mul
+            } else {
+                ArithmeticOp::Div
+            };
+            node = Node::Arithmetic(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.accept_symbol(&["-"]).is_some() {
+            return Ok(Node::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Node>, String> {
+        let mut arguments = vec![];
+        if self.accept_symbol(&[")"]).is_some() {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.or()?);
+            if self.accept_symbol(&[")"]).is_some() {
+                return Ok(arguments);
+            }
+            self.expect_symbol(",")?;
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Node::Literal(n)),
+            Some(Token::Str(s)) => Ok(Node::Literal(Variant::String(s))),
+            Some(Token::Field(f)) => Ok(Node::Field(f)),
+            Some(Token::Symbol("(")) => {
+                let node = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "null" => Ok(Node::Literal(Variant::Null)),
+                "true" => Ok(Node::Literal(Variant::Int(1))),
+                "false" => Ok(Node::Literal(Variant::Int(0))),
+                _ if self.accept_symbol(&["("]).is_some() => self.call(&name),
+                _ => Ok(Node::Field(name)),
+            },
+            token => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Node, String> {
+        let mut arguments = self.arguments()?;
+        if name == "matches" {
+            // the pattern must be a literal, so it is compiled only once
+            return match arguments.pop() {
+                Some(Node::Literal(Variant::String(pattern))) if arguments.len() == 1 => {
+                    let regex = Regex::new(&pattern).map_err(|e| e.to_string())?;
+                    Ok(Node::Matches(Box::new(arguments.pop().unwrap()), regex))
+                }
+                _ => Err("matches needs a value and a literal pattern".to_string()),
+            };
+        }
+        let (function, arity) =
+            Function::parse(name).ok_or_else(|| format!("unknown function {}", name))?;
+        if arity.is_some_and(|arity| arity != arguments.len()) {
+            return Err(format!(
+                "{} needs {} arguments, found {}",
+                name,
+                arity.unwrap(),
+                arguments.len()
+            ));
+        }
+        Ok(Node::Call(function, arguments))
+    }
+}
+
+pub fn is_true(value: &Variant) -> bool {
+    match value {
+        Variant::Null => false,
+        Variant::Int(i) => *i != 0,
+        Variant::Float(f) => *f != 0.0,
+        Variant::String(s) => !s.is_empty(),
+        Variant::Bool(b) => *b,
+        Variant::Decimal(d) => !d.is_zero(),
+        Variant::Bytes(b) => !b.is_empty(),
+        Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_) => true,
+    }
+}
+
+fn from_bool(value: bool) -> Variant {
+    Variant::Int(value as i64)
+}
+
+fn compare(op: CompareOp, v1: &Variant, v2: &Variant) -> Variant {
+    // dates are written as strings in expressions, e.g. `born < '2000-01-01'`
+    let cast;
+    let (v1, v2) = match (v1, v2) {
+        (Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_), Variant::String(_)) => {
+            cast = VariantKind::of(v1).cast(v2);
+            (v1, &cast)
+        }
+        (Variant::String(_), Variant::Date(_) | Variant::DateTime(_) | Variant::Time(_)) => {
+            cast = VariantKind::of(v2).cast(v1);
+            (&cast, v2)
+        }
+        _ => (v1, v2),
+    };
+    let ordering = match (op, v1, v2) {
+        (CompareOp::Eq, _, _) => return from_bool(v1.compare(v2) == Ordering::Equal),
+        (CompareOp::Ne, _, _) => return from_bool(v1.compare(v2) != Ordering::Equal),
+        // null and values of different types have no order
+        (_, Variant::Null, _) | (_, _, Variant::Null) => return Variant::Null,
+        (_, Variant::String(_), Variant::String(_)) => v1.compare(v2),
+        (_, Variant::String(_), _) | (_, _, Variant::String(_)) => return Variant::Null,
+        _ => v1.compare(v2),
+    };
+    from_bool(match op {
+        CompareOp::Lt => ordering == Ordering::Less,
+        CompareOp::Le => ordering != Ordering::Greater,
+        CompareOp::Gt => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    })
+}
+
+fn arithmetic(op: ArithmeticOp, v1: &Variant, v2: &Variant) -> Variant {
+    match (op, v1, v2) {
+        (ArithmeticOp::Add, Variant::Int(i1), Variant::Int(i2)) => {
+            i1.checked_add(*i2).map_or(Variant::Null, Variant::Int)
+        }
+        (ArithmeticOp::Sub, Variant::Int(i1), Variant::Int(i2)) => {
+            i1.checked_sub(*i2).map_or(Variant::Null, Variant::Int)
+        }
+        (ArithmeticOp::Mul, Variant::Int(i1), Variant::Int(i2)) => {
+            i1.checked_mul(*i2).map_or(Variant::Null, Variant::Int)
+        }
+        // decimals stay exact, ints are promoted to them
+        (_, Variant::Decimal(_), Variant::Int(_) | Variant::Decimal(_))
+        | (_, Variant::Int(_), Variant::Decimal(_)) => {
+            let decimal = |v: &Variant| match v {
+                Variant::Int(i) => Decimal::from(*i),
+                Variant::Decimal(d) => *d,
+                _ => unreachable!(),
+            };
+            let (d1, d2) = (decimal(v1), decimal(v2));
+            let result = match op {
+                ArithmeticOp::Add => d1.checked_add(d2),
+                ArithmeticOp::Sub => d1.checked_sub(d2),
+                ArithmeticOp::Mul => d1.checked_mul(d2),
+                ArithmeticOp::Div => d1.checked_div(d2),
+            };
+            result.map_or(Variant::Null, Variant::Decimal)
+        }
+        _ => match (v1.as_f64(), v2.as_f64()) {
+            (Some(f1), Some(f2)) => match op {
+                ArithmeticOp::Add => Variant::Float(f1 + f2),
+                ArithmeticOp::Sub => Variant::Float(f1 - f2),
+                ArithmeticOp::Mul => Variant::Float(f1 * f2),
+                ArithmeticOp::Div if f2 == 0.0 => Variant::Null,
+                ArithmeticOp::Div => Variant::Float(f1 / f2),
+            },
+            _ => Variant::Null,
+        },
+    }
+}
+
+fn call(function: Function, arguments: Vec<Variant>) -> Variant {
+    match function {
+        Function::Coalesce => arguments
+            .into_iter()
+            .find(|a| *a != Variant::Null)
+            .unwrap_or(Variant::Null),
+        Function::Concat => Variant::String(
+            arguments
+                .iter()
+                .filter(|a| **a != Variant::Null)
+                .map(|a| a.to_string())
+                .collect(),
+        ),
+        _ if arguments.contains(&Variant::Null) => Variant::Null,
+        _ => {
+            let text = arguments[0].to_string();
+            match function {
+                Function::Lower => Variant::String(text.to_lowercase()),
+                Function::Upper => Variant::String(text.to_uppercase()),
+                Function::Trim => Variant::String(text.trim().to_string()),
+                Function::Length => Variant::Int(text.chars().count() as i64),
+                Function::Contains => from_bool(text.contains(&arguments[1].to_string())),
+                Function::StartsWith => from_bool(text.starts_with(&arguments[1].to_string())),
+                Function::EndsWith => from_bool(text.ends_with(&arguments[1].to_string())),
+                Function::Coalesce | Function::Concat => unreachable!(),
+            }
+        }
+    }
+}
+
+fn evaluate(node: &Node, record: &Record) -> Variant {
+    match node {
+        Node::Literal(value) => value.clone(),
+        Node::Field(name) => record.fields.get(name).cloned().unwrap_or(Variant::Null),
+        Node::Not(node) => from_bool(!is_true(&evaluate(node, record))),
+        Node::And(n1, n2) => {
+            from_bool(is_true(&evaluate(n1, record)) && is_true(&evaluate(n2, record)))
+        }
+        Node::Or(n1, n2) => {
+            from_bool(is_true(&evaluate(n1, record)) || is_true(&evaluate(n2, record)))
+        }
+        Node::Compare(op, n1, n2) => compare(*op, &evaluate(n1, record), &evaluate(n2, record)),
+        Node::Arithmetic(op, n1, n2) => {
+            arithmetic(*op, &evaluate(n1, record), &evaluate(n2, record))
+        }
+        Node::Negate(node) => match evaluate(node, record) {
+            Variant::Int(i) => i.checked_neg().map_or(Variant::Null, Variant::Int),
+            Variant::Float(f) => Variant::Float(-f),
+            Variant::Decimal(d) => Variant::Decimal(-d),
+            Variant::Bool(b) => Variant::Int(-(b as i64)),
+            _ => Variant::Null,
+        },
+        Node::IsNull(node, negated) => {
+            from_bool((evaluate(node, record) == Variant::Null) != *negated)
+        }
+        Node::Call(function, arguments) => call(
+            *function,
+            arguments.iter().map(|a| evaluate(a, record)).collect(),
+        ),
+        Node::Matches(node, regex) => match evaluate(node, record) {
+            Variant::Null => Variant::Null,
+            value => from_bool(regex.is_match(&value.to_string())),
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let root = parser.or()?;
+        match parser.peek() {
+            None => Ok(Expression { root }),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    pub fn evaluate(&self, record: &Record) -> Variant {
+        evaluate(&self.root, record)
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        is_true(&self.evaluate(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn make_record() -> Record {
+        let mut fields = IndexMap::new();
+        fields.insert("name".to_string(), Variant::String(" Alice ".to_string()));
+        fields.insert("age".to_string(), Variant::Int(30));
+        fields.insert("height".to_string(), Variant::Float(1.7));
+        fields.insert("email".to_string(), Variant::Null);
+        fields.insert("first name".to_string(), Variant::String("A".to_string()));
+        Record { fields }
+    }
+
+    fn eval(text: &str) -> Variant {
+        Expression::parse(text).unwrap().evaluate(&make_record())
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        assert_eq!(eval("age >= 18 and height < 2"), Variant::Int(1));
+        assert_eq!(eval("age = 30.0"), Variant::Int(1));
+        assert_eq!(eval("not (age > 40 || height > 1.8)"), Variant::Int(1));
+        assert_eq!(eval("!(age != 30)"), Variant::Int(1));
+        assert_eq!(eval("`first name` == \"A\""), Variant::Int(1));
+        assert_eq!(eval("age * 2 + 1 - 6 / 2"), Variant::Float(58.0));
+        assert_eq!(eval("(age - 1) * 2"), Variant::Int(58));
+        assert_eq!(eval("-age"), Variant::Int(-30));
+        // overflows are unknown, as with the other operators
+        assert_eq!(eval("-(-9223372036854775807 - 1)"), Variant::Null);
+        // ordering against null or between numbers and strings is unknown
+        assert_eq!(eval("email < 3"), Variant::Null);
+        assert_eq!(eval("name > 3"), Variant::Null);
+        assert_eq!(eval("missing == null"), Variant::Int(1));
+    }
+
+    #[test]
+    fn test_null_checks_and_functions() {
+        assert_eq!(eval("email is null and name is not null"), Variant::Int(1));
+        assert_eq!(
+            eval("lower(trim(name))"),
+            Variant::String("alice".to_string())
+        );
+        assert_eq!(eval("upper(email)"), Variant::Null);
+        assert_eq!(eval("length(name)"), Variant::Int(7));
+        assert_eq!(eval("contains(name, 'lic')"), Variant::Int(1));
+        assert_eq!(eval("starts_with(trim(name), 'Al')"), Variant::Int(1));
+        assert_eq!(eval("ends_with(name, 'x')"), Variant::Int(0));
+        assert_eq!(eval("matches(age, '^3[0-9]$')"), Variant::Int(1));
+        assert_eq!(
+            eval("coalesce(email, 'none')"),
+            Variant::String("none".to_string())
+        );
+        assert_eq!(
+            eval("concat(trim(name), '-', age, email)"),
+            Variant::String("Alice-30".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        let mut record = make_record();
+        let amount = Decimal::new(1010, 2);
+        record
+            .fields
+            .insert("amount".to_string(), Variant::Decimal(amount));
+        record
+            .fields
+            .insert("paid".to_string(), Variant::Bool(true));
+        record
+            .fields
+            .insert("max".to_string(), Variant::Decimal(Decimal::MAX));
+        let eval = |text: &str| Expression::parse(text).unwrap().evaluate(&record);
+        assert_eq!(eval("-amount"), Variant::Decimal(-amount));
+        assert_eq!(eval("-amount < 0"), Variant::Int(1));
+        assert_eq!(eval("-paid"), Variant::Int(-1));
+        assert_eq!(eval("amount + 2"), Variant::Decimal(Decimal::new(1210, 2)));
+        assert_eq!(eval("amount - amount"), Variant::Decimal(Decimal::ZERO));
+        assert_eq!(eval("3 * amount"), Variant::Decimal(Decimal::new(3030, 2)));
+        assert_eq!(eval("amount / 4"), Variant::Decimal(Decimal::new(2525, 3)));
+        // as floats 0.1 * 3 would be 0.30000000000000004
+        assert_eq!(
+            eval("amount / 101 * 3"),
+            Variant::Decimal(Decimal::new(3, 1))
+        );
+        assert_eq!(eval("amount / 0"), Variant::Null);
+        assert_eq!(eval("max + 1"), Variant::Null);
+        assert_eq!(eval("amount + height"), Variant::Float(10.1 + 1.7));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::parse("age >").is_err());
+        assert!(Expression::parse("age > 1 1").is_err());
+        assert!(Expression::parse("unknown(age)").is_err());
+        assert!(Expression::parse("lower(age, 1)").is_err());
+        assert!(Expression::parse("matches(name, email)").is_err());
+        assert!(Expression::parse("name == 'open").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Table};
+use crate::transform::Expression;
+use serde_json::{Map, Value};
+
+/// Copies the records of `input` that match `expression` into a table named after the node.
+pub struct TransformFilter {
+    node_name: String,
+    input: String,
+    expression: Expression,
+}
+
+impl TransformFilter {
+    pub fn filter(&self, table: &Table) -> Table {
+        Table {
+            name: self.node_name.clone(),
+            records: table
+                .records
+                .iter()
+                .filter(|r| self.expression.matches(r))
+                .map(|r| Record {
+                    fields: r.fields.clone(),
+                })
+                .collect(),
+            columns: table.columns.clone(),
+            schema: table.schema.clone(),
+        }
+    }
+}
+
+impl Process for TransformFilter {
+    register_process!(transform::filter);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let expression = read_config_field(&config, "expression");
+        TransformFilter {
+            node_name,
+            input: read_config_field(&config, "input"),
+            expression: Expression::parse(&expression)
+                .unwrap_or_else(|e| panic!("Invalid expression {}: {}", expression, e)),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        let filtered = self.filter(table);
+        state.tables.push(filtered);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Variant;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_filter() {
+        let records = [
+            ("Alice", Variant::Int(30)),
+            ("Bob", Variant::Null),
+            ("Carl", Variant::Int(12)),
+        ]
+        .into_iter()
+        .map(|(name, age)| {
+            let mut fields = IndexMap::new();
+            fields.insert("name".to_string(), Variant::String(name.to_string()));
+            fields.insert("age".to_string(), age);
+            Record { fields }
+        })
+        .collect();
+        let people = Table {
+            name: "people".to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        };
+        let config: Value = serde_json::from_str(
+            r#"{"input":"people","expression":"age is null or (age >= 18 and starts_with(name, 'A'))"}"#,
+        )
+        .unwrap();
+        let filter =
+            TransformFilter::from_config("adults".to_string(), config.as_object().unwrap().clone());
+        let adults = filter.filter(&people);
+        assert_eq!(adults.name, "adults");
+        let names: Vec<&Variant> = adults.records.iter().map(|r| &r.fields["name"]).collect();
+        assert_eq!(
+            names,
+            vec![
+                &Variant::String("Alice".to_string()),
+                &Variant::String("Bob".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_on_dates() {
+        let records = ["2001-09-09", "1999-12-31"]
+            .into_iter()
+            .map(|born| {
+                let mut fields = IndexMap::new();
+                let born = chrono::NaiveDate::parse_from_str(born, "%Y-%m-%d").unwrap();
+                fields.insert("born".to_string(), Variant::Date(born));
+                Record { fields }
+            })
+            .collect();
+        let people = Table {
+            name: "people".to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        };
+        let filter = |expression: &str| {
+            let config = serde_json::json!({"input": "people", "expression": expression});
+            TransformFilter::from_config("out".to_string(), config.as_object().unwrap().clone())
+                .filter(&people)
+                .records
+                .len()
+        };
+        assert_eq!(filter("born >= '2000-01-01'"), 1);
+        assert_eq!(filter("'2000-01-01' > born"), 1);
+        assert_eq!(filter("born = '1999-12-31'"), 1);
+        assert_eq!(filter("born != '1999-12-31'"), 1);
+        // a string that is not a date cannot be ordered against one
+        assert_eq!(filter("born < 'yesterday'"), 0);
+        assert_eq!(filter("(born < 'yesterday') is null"), 2);
+    }
+}
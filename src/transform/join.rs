@@ -0,0 +1,432 @@
+use crate::register_process;
+use crate::state::{
+    read_config_field, read_fields_list, Factory, Process, Record, State, Table, Variant,
+};
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl JoinType {
+    fn parse(value: &str) -> Self {
+        match value {
+            "inner" => JoinType::Inner,
+            "left" => JoinType::Left,
+            "right" => JoinType::Right,
+            "full" => JoinType::Full,
+            _ => panic!("Unknown join type {}", value),
+        }
+    }
+}
+
+/// What to do with the fields, other than the join keys, found in both tables.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OnCollision {
+    /// keep both, renamed with `left_prefix` and `right_prefix`
+    Prefix,
+    /// keep the value of the left record, the right one is used only without a left record
+    Left,
+    /// keep the value of the right record, the left one is used only without a right record
+    Right,
+    Error,
+}
+
+impl OnCollision {
+    fn parse(value: &str) -> Self {
+        match value {
+            "prefix" => OnCollision::Prefix,
+            "left" => OnCollision::Left,
+            "right" => OnCollision::Right,
+            "error" => OnCollision::Error,
+            _ => panic!("Unknown on_collision policy {}", value),
+        }
+    }
+}
+
+// a field of the joined table, and where its value comes from
+struct JoinedColumn {
+    name: String,
+    left: Option<String>,
+    right: Option<String>,
+    prefer_left: bool,
+}
+
+pub struct TransformJoin {
+    node_name: String,
+    left: String,
+    right: String,
+    left_on: Vec<String>,
+    right_on: Vec<String>,
+    join_type: JoinType,
+    on_collision: OnCollision,
+    left_prefix: String,
+    right_prefix: String,
+}
+
+fn field_names(table: &Table) -> Vec<String> {
+    table
+        .column_names()
+        .into_iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+fn compare_keys(k1: &[&Variant], k2: &[&Variant]) -> Ordering {
+    k1.iter()
+        .zip(k2.iter())
+        .map(|(a, b)| a.compare(b))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+// the join key of a record, None when a key field is null or missing, as those never match
+fn key<'a>(record: &'a Record, fields: &[String]) -> Option<Vec<&'a Variant>> {
+    fields
+        .iter()
+        .map(|f| record.fields.get(f).filter(|v| **v != Variant::Null))
+        .collect()
+}
+
+impl TransformJoin {
+    fn columns(&self, left: &Table, right: &Table) -> Vec<JoinedColumn> {
+        // keys with the same name on both sides become a single field
+        let shared_keys: HashSet<&String> = self
+            .left_on
+            .iter()
+            .zip(self.right_on.iter())
+            .filter(|(l, r)| l == r)
+            .map(|(l, _)| l)
+            .collect();
+        let left_fields = field_names(left);
+        let right_fields = field_names(right);
+        let collisions: Vec<&String> = left_fields
+            .iter()
+            .filter(|f| !shared_keys.contains(*f) && right_fields.contains(f))
+            .collect();
+        if self.on_collision == OnCollision::Error && !collisions.is_empty() {
+            panic!(
+                "fields {} are in both {} and {}",
+                collisions.iter().join(", "),
+                self.left,
+                self.right
+            );
+        }
+
+        let mut columns = vec![];
+        for field in &left_fields {
+            let collides = collisions.contains(&field);
+            if shared_keys.contains(field) || (collides && self.on_collision != OnCollision::Prefix)
+            {
+                columns.push(JoinedColumn {
+                    name: field.clone(),
+                    left: Some(field.clone()),
+                    right: Some(field.clone()),
+                    prefer_left: self.on_collision != OnCollision::Right
+                        || shared_keys.contains(field),
+                });
+            } else {
+                columns.push(JoinedColumn {
+                    name: if collides {
+                        format!("{}{}", self.left_prefix, field)
+                    } else {
+                        field.clone()
+                    },
+                    left: Some(field.clone()),
+                    right: None,
+                    prefer_left: true,
+                });
+            }
+        }
+        for field in &right_fields {
+            let collides = collisions.contains(&field);
+            if shared_keys.contains(field) || (collides && self.on_collision != OnCollision::Prefix)
+            {
+                continue;
+            }
+            columns.push(JoinedColumn {
+                name: if collides {
+                    format!("{}{}", self.right_prefix, field)
+                } else {
+                    field.clone()
+                },
+                left: None,
+                right: Some(field.clone()),
+                prefer_left: false,
+            });
+        }
+        columns
+    }
+
+    fn merge(
+        &self,
+        columns: &[JoinedColumn],
+        left: Option<&Record>,
+        right: Option<&Record>,
+    ) -> Record {
+        let value = |record: Option<&Record>, field: &Option<String>| match (record, field) {
+            (Some(record), Some(field)) => {
+                Some(record.fields.get(field).cloned().unwrap_or(Variant::Null))
+            }
+            _ => None,
+        };
+        Record {
+            fields: columns
+                .iter()
+                .map(|column| {
+                    let left_value = value(left, &column.left);
+                    let right_value = value(right, &column.right);
+                    let joined = if column.prefer_left {
+                        left_value.or(right_value)
+                    } else {
+                        right_value.or(left_value)
+                    };
+                    (column.name.clone(), joined.unwrap_or(Variant::Null))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn join(&self, left: &Table, right: &Table) -> Table {
+        let columns = self.columns(left, right);
+        let right_keys: Vec<(Vec<&Variant>, usize)> = right
+            .records
+            .iter()
+            .enumerate()
+            .filter_map(|(index, record)| key(record, &self.right_on).map(|k| (k, index)))
+            .sorted_by(|(k1, i1), (k2, i2)| compare_keys(k1, k2).then(i1.cmp(i2)))
+            .collect();
+        let mut matched_right = vec![false; right.records.len()];
+        let mut records = vec![];
+        for record in &left.records {
+            let matches: &[(Vec<&Variant>, usize)] = match key(record, &self.left_on) {
+                Some(k) => {
+                    let start =
+                        right_keys.partition_point(|(r, _)| compare_keys(r, &k) == Ordering::Less);
+                    let end = right_keys
+                        .partition_point(|(r, _)| compare_keys(r, &k) != Ordering::Greater);
+                    &right_keys[start..end]
+                }
+                None => &[],
+            };
+            for (_, index) in matches {
+                matched_right[*index] = true;
+                records.push(self.merge(&columns, Some(record), Some(&right.records[*index])));
+            }
+            if matches.is_empty() && matches!(self.join_type, JoinType::Left | JoinType::Full) {
+                records.push(self.merge(&columns, Some(record), None));
+            }
+        }
+        if matches!(self.join_type, JoinType::Right | JoinType::Full) {
+            for (record, matched) in right.records.iter().zip(matched_right) {
+                if !matched {
+                    records.push(self.merge(&columns, None, Some(record)));
+                }
+            }
+        }
+        Table {
+            name: self.node_name.clone(),
+            records,
+            columns: columns.into_iter().map(|c| c.name).collect(),
+            schema: None,
+        }
+    }
+}
+
+impl Process for TransformJoin {
+    register_process!(transform::join);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let left: String = read_config_field(&config, "left");
+        let right: String = read_config_field(&config, "right");
+        let on = read_fields_list(&config, "on");
+        let (left_on, right_on) = if on.is_empty() {
+            (
+                read_fields_list(&config, "left_on"),
+                read_fields_list(&config, "right_on"),
+            )
+        } else {
+            (on.clone(), on)
+        };
+        if left_on.is_empty() || left_on.len() != right_on.len() {
+            panic!("join needs on, or left_on and right_on with the same number of fields");
+        }
+        let option = |name: &str, default: String| {
+            config
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .unwrap_or(default)
+        };
+        TransformJoin {
+            node_name,
+            left_on,
+            right_on,
+            join_type: JoinType::parse(&option("type", "inner".to_string())),
+            on_collision: OnCollision::parse(&option("on_collision", "prefix".to_string())),
+            left_prefix: option("left_prefix", format!("{}_", left)),
+            right_prefix: option("right_prefix", format!("{}_", right)),
+            left,
+            right,
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let left = state.find_table(&self.left).unwrap();
+        let right = state.find_table(&self.right).unwrap();
+        let joined = self.join(left, right);
+        state.tables.push(joined);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn make_table(name: &str, columns: &[&str], rows: &[Vec<Variant>]) -> Table {
+        Table {
+            name: name.to_string(),
+            records: rows
+                .iter()
+                .map(|row| Record {
+                    fields: columns
+                        .iter()
+                        .map(|c| c.to_string())
+                        .zip(row.iter().cloned())
+                        .collect::<IndexMap<String, Variant>>(),
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn s(value: &str) -> Variant {
+        Variant::String(value.to_string())
+    }
+
+    fn customers() -> Table {
+        make_table(
+            "customers",
+            &["id", "name"],
+            &[
+                vec![Variant::Int(1), s("Ada")],
+                vec![Variant::Int(2), s("Alan")],
+                vec![Variant::Null, s("Nobody")],
+            ],
+        )
+    }
+
+    fn orders() -> Table {
+        make_table(
+            "orders",
+            &["id", "name", "total"],
+            &[
+                vec![Variant::Int(1), s("book"), Variant::Int(10)],
+                vec![Variant::Float(1.0), s("pen"), Variant::Int(2)],
+                vec![Variant::Int(3), s("ink"), Variant::Int(5)],
+            ],
+        )
+    }
+
+    fn make_join(config: &str) -> TransformJoin {
+        let config: Value = serde_json::from_str(config).unwrap();
+        TransformJoin::from_config("joined".to_string(), config.as_object().unwrap().clone())
+    }
+
+    fn rows(table: &Table, columns: &[&str]) -> Vec<Vec<Variant>> {
+        table
+            .records
+            .iter()
+            .map(|r| columns.iter().map(|c| r.fields[*c].clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_join_types() {
+        let columns = ["id", "customers_name", "orders_name", "total"];
+        let inner = make_join(r#"{"left":"customers","right":"orders","on":"id"}"#)
+            .join(&customers(), &orders());
+        assert_eq!(inner.name, "joined");
+        assert_eq!(
+            rows(&inner, &columns),
+            vec![
+                vec![Variant::Int(1), s("Ada"), s("book"), Variant::Int(10)],
+                vec![Variant::Int(1), s("Ada"), s("pen"), Variant::Int(2)],
+            ]
+        );
+
+        let full = make_join(r#"{"left":"customers","right":"orders","on":"id","type":"full"}"#)
+            .join(&customers(), &orders());
+        assert_eq!(
+            rows(&full, &columns)[2..],
+            vec![
+                vec![Variant::Int(2), s("Alan"), Variant::Null, Variant::Null],
+                vec![Variant::Null, s("Nobody"), Variant::Null, Variant::Null],
+                vec![Variant::Int(3), Variant::Null, s("ink"), Variant::Int(5)],
+            ]
+        );
+
+        let left = make_join(r#"{"left":"customers","right":"orders","on":"id","type":"left"}"#)
+            .join(&customers(), &orders());
+        assert_eq!(left.records.len(), 4);
+        let right = make_join(r#"{"left":"customers","right":"orders","on":"id","type":"right"}"#)
+            .join(&customers(), &orders());
+        assert_eq!(right.records.len(), 3);
+    }
+
+    #[test]
+    fn test_composite_keys_and_collisions() {
+        let prices = make_table(
+            "prices",
+            &["sku", "shop", "price"],
+            &[
+                vec![s("a"), s("x"), Variant::Int(1)],
+                vec![s("a"), s("y"), Variant::Int(2)],
+            ],
+        );
+        let stock = make_table(
+            "stock",
+            &["product", "store", "price", "qty"],
+            &[vec![s("a"), s("y"), Variant::Int(3), Variant::Int(7)]],
+        );
+        let join = make_join(
+            r#"{"left":"prices","right":"stock","left_on":["sku","shop"],"right_on":["product","store"],"on_collision":"right","type":"left"}"#,
+        );
+        let joined = join.join(&prices, &stock);
+        assert_eq!(
+            rows(&joined, &["sku", "shop", "product", "price", "qty"]),
+            vec![
+                vec![
+                    s("a"),
+                    s("x"),
+                    Variant::Null,
+                    Variant::Int(1),
+                    Variant::Null
+                ],
+                vec![s("a"), s("y"), s("a"), Variant::Int(3), Variant::Int(7)],
+            ]
+        );
+        let prefixed = make_join(
+            r#"{"left":"prices","right":"stock","left_on":["sku","shop"],"right_on":["product","store"],"left_prefix":"l.","right_prefix":"r."}"#,
+        )
+        .join(&prices, &stock);
+        assert_eq!(
+            rows(&prefixed, &["l.price", "r.price"]),
+            vec![vec![Variant::Int(2), Variant::Int(3)]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "fields name are in both customers and orders")]
+    fn test_collision_error() {
+        make_join(r#"{"left":"customers","right":"orders","on":"id","on_collision":"error"}"#)
+            .join(&customers(), &orders());
+    }
+}
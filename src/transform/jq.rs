@@ -0,0 +1,212 @@
+use crate::register_process;
+use crate::state::{read_config_field, Factory, Process, Record, State, Table, Variant};
+use indexmap::IndexMap;
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{data, unwrap_valr, Compiler, Ctx, Filter, Vars};
+use jaq_json::{read, Val};
+use serde_json::{Map, Value};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JqMode {
+    /// the program runs once for every record
+    Record,
+    /// the program runs once with the array of all the records
+    Table,
+}
+
+impl JqMode {
+    fn parse(value: &str) -> Self {
+        match value {
+            "record" => JqMode::Record,
+            "table" => JqMode::Table,
+            _ => panic!("Unknown jq mode {}", value),
+        }
+    }
+}
+
+/// Builds a table named after the node from the objects produced by a jq program.
+pub struct TransformJq {
+    node_name: String,
+    input: String,
+    mode: JqMode,
+    filter: Filter<data::JustLut<Val>>,
+}
+
+fn compile(program: &str) -> Result<Filter<data::JustLut<Val>>, String> {
+    let defs = jaq_core::defs()
+        .chain(jaq_std::defs())
+        .chain(jaq_json::defs());
+    let funs = jaq_core::funs()
+        .chain(jaq_std::funs())
+        .chain(jaq_json::funs());
+    let arena = Arena::default();
+    let modules = Loader::new(defs)
+        .load(
+            &arena,
+            File {
+                code: program,
+                path: (),
+            },
+        )
+        .map_err(|e| format!("{:?}", e))?;
+    Compiler::default()
+        .with_funs(funs)
+        .compile(modules)
+        .map_err(|e| format!("{:?}", e))
+}
+
+// arrays and objects are kept as json text
+fn to_variant(value: &Value) -> Variant {
+    match value {
+        Value::Array(_) | Value::Object(_) => Variant::String(value.to_string()),
+        _ => Variant::from_serde_value(value),
+    }
+}
+
+fn to_record(value: Value) -> Record {
+    match value {
+        Value::Object(object) => Record {
+            fields: object
+                .iter()
+                .map(|(k, v)| (k.clone(), to_variant(v)))
+                .collect::<IndexMap<String, Variant>>(),
+        },
+        _ => panic!("jq program must produce objects, found {}", value),
+    }
+}
+
+impl TransformJq {
+    fn run_program(&self, input: Value) -> Vec<Value> {
+        let input = read::parse_single(input.to_string().as_bytes()).unwrap();
+        let ctx = Ctx::<data::JustLut<Val>>::new(&self.filter.lut, Vars::new([]));
+        self.filter
+            .id
+            .run((ctx, input))
+            .map(unwrap_valr)
+            .map(|output| match output {
+                Ok(value) => serde_json::from_str(&value.to_string()).unwrap(),
+                Err(e) => panic!("jq error in node {}: {}", self.node_name, e),
+            })
+            .collect()
+    }
+
+    pub fn transform(&self, table: &Table) -> Table {
+        let records = match self.mode {
+            JqMode::Record => table
+                .records
+                .iter()
+                .flat_map(|r| self.run_program(serde_json::to_value(r).unwrap()))
+                .map(to_record)
+                .collect(),
+            JqMode::Table => self
+                .run_program(serde_json::to_value(&table.records).unwrap())
+                .into_iter()
+                .flat_map(|output| match output {
+                    Value::Array(values) => values,
+                    value => vec![value],
+                })
+                .map(to_record)
+                .collect(),
+        };
+        Table {
+            name: self.node_name.clone(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+}
+
+impl Process for TransformJq {
+    register_process!(transform::jq);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let program = read_config_field(&config, "program");
+        TransformJq {
+            node_name,
+            input: read_config_field(&config, "input"),
+            mode: JqMode::parse(
+                config
+                    .get("mode")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("record"),
+            ),
+            filter: compile(&program)
+                .unwrap_or_else(|e| panic!("Invalid jq program {}: {}", program, e)),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        let transformed = self.transform(table);
+        state.tables.push(transformed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_people() -> Table {
+        let records = [("Ada", "Lovelace", 36), ("Alan", "Turing", 17)]
+            .into_iter()
+            .map(|(first, last, age)| {
+                let mut fields = IndexMap::new();
+                fields.insert("first".to_string(), Variant::String(first.to_string()));
+                fields.insert("last".to_string(), Variant::String(last.to_string()));
+                fields.insert("age".to_string(), Variant::Int(age));
+                Record { fields }
+            })
+            .collect();
+        Table {
+            name: "people".to_string(),
+            records,
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn make_jq(config: &str) -> TransformJq {
+        let config: Value = serde_json::from_str(config).unwrap();
+        TransformJq::from_config("out".to_string(), config.as_object().unwrap().clone())
+    }
+
+    #[test]
+    fn test_record_mode() {
+        let jq = make_jq(
+            r#"{"input":"people","program":"select(.age > 10) | {name: \"\\(.first) \\(.last)\", surname: .last, months: (.age * 12), adult: (if .age >= 18 then \"yes\" else \"no\" end), tags: [.first]}"}"#,
+        );
+        let table = jq.transform(&make_people());
+        assert_eq!(table.name, "out");
+        assert_eq!(table.records.len(), 2);
+        let ada = &table.records[0].fields;
+        assert_eq!(ada["name"], Variant::String("Ada Lovelace".to_string()));
+        assert_eq!(ada["surname"], Variant::String("Lovelace".to_string()));
+        assert_eq!(ada["months"], Variant::Int(432));
+        assert_eq!(ada["adult"], Variant::String("yes".to_string()));
+        assert_eq!(ada["tags"], Variant::String("[\"Ada\"]".to_string()));
+        assert_eq!(
+            table.records[1].fields["adult"],
+            Variant::String("no".to_string())
+        );
+    }
+
+    #[test]
+    fn test_table_mode() {
+        let jq = make_jq(
+            r#"{"input":"people","mode":"table","program":"[.[] | select(.age >= 18) | {first, old: (.age > 30)}]"}"#,
+        );
+        let table = jq.transform(&make_people());
+        assert_eq!(table.records.len(), 1);
+        assert_eq!(
+            table.records[0].fields["first"],
+            Variant::String("Ada".to_string())
+        );
+        assert_eq!(table.records[0].fields["old"], Variant::Bool(true));
+        assert_eq!(table.records[0].fields.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid jq program")]
+    fn test_invalid_program() {
+        make_jq(r#"{"input":"people","program":"{a: "}"#);
+    }
+}
@@ -0,0 +1,15 @@
+mod aggregate;
+mod expression;
+mod filter;
+mod jq;
+mod join;
+mod select;
+mod union;
+
+pub use aggregate::*;
+pub use expression::*;
+pub use filter::*;
+pub use jq::*;
+pub use join::*;
+pub use select::*;
+pub use union::*;
@@ -0,0 +1,297 @@
+use crate::register_process;
+use crate::state::{
+    read_config_field, read_fields_list, ColumnSchema, Factory, Process, Record, Schema, State,
+    Table, VariantKind,
+};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Matches a field name against a pattern where `*` stands for any sequence of characters
+/// and `?` for a single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // position in the pattern and in the name after the last `*`, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('?')
+}
+
+/// Builds a table named after the node with some of the fields of `input`, in this order:
+/// the fields matching `select` (all of them when missing) are kept in the order of the
+/// patterns, the ones matching `drop` are removed, `rename` maps old names to new ones, that
+/// must not collide, and the fields matching `order` are moved first. The resulting column
+/// order is kept by the outputs.
+pub struct TransformSelect {
+    node_name: String,
+    input: String,
+    select: Vec<String>,
+    drop: Vec<String>,
+    rename: HashMap<String, String>,
+    order: Vec<String>,
+}
+
+impl TransformSelect {
+    // pairs of (source field, output field)
+    fn columns(&self, table: &Table) -> Vec<(String, String)> {
+        let available = table.column_names();
+        let mut columns: Vec<&str> = vec![];
+        if self.select.is_empty() {
+            columns = available.clone();
+        }
+        for pattern in &self.select {
+            if is_glob(pattern) {
+                for column in &available {
+                    if glob_match(pattern, column) && !columns.contains(column) {
+                        columns.push(column);
+                    }
+                }
+            } else if !columns.contains(&pattern.as_str()) {
+                // a field missing from every record is still a column of the table, records
+                // are left without it and the outputs show it as null
+                columns.push(pattern);
+            }
+        }
+        columns.retain(|c| !self.drop.iter().any(|pattern| glob_match(pattern, c)));
+        let mut columns: Vec<(String, String)> = columns
+            .into_iter()
+            .map(|c| {
+                let name = self.rename.get(c).map_or(c, |n| n.as_str());
+                (c.to_string(), name.to_string())
+            })
+            .collect();
+        // renames are checked against the selected fields when parsed, globs can still collide
+        for (index, (_, name)) in columns.iter().enumerate() {
+            if columns[..index].iter().any(|(_, n)| n == name) {
+                panic!("several fields are named {} after renaming", name);
+            }
+        }
+        let mut ordered = vec![];
+        for pattern in &self.order {
+            let (matching, others): (Vec<_>, Vec<_>) = columns
+                .into_iter()
+                .partition(|(_, name)| glob_match(pattern, name));
+            ordered.extend(matching);
+            columns = others;
+        }
+        ordered.extend(columns);
+        ordered
+    }
+
+    pub fn select(&self, table: &Table) -> Table {
+        let columns = self.columns(table);
+        Table {
+            name: self.node_name.clone(),
+            records: table
+                .records
+                .iter()
+                .map(|record| Record {
+                    fields: columns
+                        .iter()
+                        .filter_map(|(source, name)| {
+                            record
+                                .fields
+                                .get(source)
+                                .map(|value| (name.clone(), value.clone()))
+                        })
+                        .collect(),
+                })
+                .collect(),
+            schema: table.schema.as_ref().map(|schema| Schema {
+                columns: columns
+                    .iter()
+                    .map(|(source, name)| match schema.column(source) {
+                        Some(column) => ColumnSchema {
+                            name: name.clone(),
+                            ..column.clone()
+                        },
+                        None => ColumnSchema {
+                            name: name.clone(),
+                            kind: VariantKind::Null,
+                            nullable: true,
+                            source_type: None,
+                        },
+                    })
+                    .collect(),
+            }),
+            columns: columns.into_iter().map(|(_, name)| name).collect(),
+        }
+    }
+}
+
+impl Process for TransformSelect {
+    register_process!(transform::select);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let rename = match config.get("rename") {
+            None => HashMap::new(),
+            Some(Value::Object(rename)) => rename
+                .iter()
+                .map(|(old, new)| {
+                    let new = new
+                        .as_str()
+                        .unwrap_or_else(|| panic!("the new name of {} must be a string", old));
+                    (old.clone(), new.to_string())
+                })
+                .collect(),
+            Some(_) => panic!("rename must be an object"),
+        };
+        let select = read_fields_list(&config, "select");
+        for (old, new) in &rename {
+            if rename.iter().any(|(o, n)| n == new && o != old) {
+                panic!("several fields are renamed to {}", new);
+            }
+            let kept = |f: &String| f == new && !is_glob(f) && !rename.contains_key(f);
+            if new != old && select.iter().any(kept) {
+                panic!("cannot rename {} to {}, the field already exists", old, new);
+            }
+        }
+        TransformSelect {
+            node_name,
+            input: read_config_field(&config, "input"),
+            select,
+            drop: read_fields_list(&config, "drop"),
+            rename,
+            order: read_fields_list(&config, "order"),
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let table = state.find_table(&self.input).unwrap();
+        let selected = self.select(table);
+        state.tables.push(selected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{ordered_records, OutputCsv, OutputSqlInserts};
+    use crate::state::Variant;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("name", "name"));
+        assert!(!glob_match("name", "names"));
+        assert!(glob_match("addr_*", "addr_city"));
+        assert!(glob_match("*_id", "customer_id"));
+        assert!(glob_match("a*b*c", "axxbyybc"));
+        assert!(glob_match("?d", "id"));
+        assert!(!glob_match("?d", "uid"));
+        assert!(glob_match("*", ""));
+    }
+
+    fn make_people() -> Table {
+        let mut fields = IndexMap::new();
+        for (name, value) in [
+            ("id", Variant::Int(1)),
+            ("name", Variant::String("Ada".to_string())),
+            ("addr_city", Variant::String("London".to_string())),
+            ("addr_zip", Variant::String("W1".to_string())),
+            ("tmp_flag", Variant::Int(0)),
+        ] {
+            fields.insert(name.to_string(), value);
+        }
+        Table {
+            name: "people".to_string(),
+            records: vec![Record { fields }],
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn make_select(config: &str) -> TransformSelect {
+        let config: Value = serde_json::from_str(config).unwrap();
+        TransformSelect::from_config("out".to_string(), config.as_object().unwrap().clone())
+    }
+
+    #[test]
+    fn test_select_rename_drop_order() {
+        let select = make_select(
+            r#"{"input":"people","select":["name","addr_*","id","email"],"drop":["*_zip"],"rename":{"name":"full_name"},"order":["id"]}"#,
+        );
+        let table = select.select(&make_people());
+        assert_eq!(table.name, "out");
+        assert_eq!(table.columns, vec!["id", "full_name", "addr_city", "email"]);
+        let record = &table.records[0].fields;
+        assert_eq!(record.len(), 3);
+        assert_eq!(record["full_name"], Variant::String("Ada".to_string()));
+
+        // without select every field is kept, in the order of the input
+        let table = make_select(r#"{"input":"people","drop":"tmp_*","order":["name","id"]}"#)
+            .select(&make_people());
+        assert_eq!(table.columns, vec!["name", "id", "addr_city", "addr_zip"]);
+    }
+
+    #[test]
+    fn test_column_order_reaches_outputs() {
+        let table = make_select(r#"{"input":"people","select":["name","id","email"]}"#)
+            .select(&make_people());
+        let config: Value = serde_json::from_str(r#"{"input":"out","path":"out.csv"}"#).unwrap();
+        let csv = OutputCsv::from_config("csv".to_string(), config.as_object().unwrap().clone());
+        assert_eq!(
+            csv.format_table(&table),
+            vec!["name,id,email\r\n", "Ada,1,\r\n"]
+        );
+        assert_eq!(
+            serde_json::to_string(&ordered_records(&table)).unwrap(),
+            r#"[{"name":"Ada","id":1}]"#
+        );
+        let config: Value =
+            serde_json::from_str(r#"{"input":"out","path":"out.sql","table-name":"people"}"#)
+                .unwrap();
+        let sql =
+            OutputSqlInserts::from_config("sql".to_string(), config.as_object().unwrap().clone());
+        assert_eq!(
+            sql.statements(&table),
+            vec!["insert into `people` (`name`,`id`) values ('Ada',1);"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot rename name to id, the field already exists")]
+    fn test_rename_onto_selected_field() {
+        make_select(r#"{"input":"people","select":["id","name"],"rename":{"name":"id"}}"#);
+    }
+
+    #[test]
+    #[should_panic(expected = "several fields are renamed to label")]
+    fn test_renames_onto_same_name() {
+        make_select(r#"{"input":"people","rename":{"name":"label","addr_city":"label"}}"#);
+    }
+
+    #[test]
+    #[should_panic(expected = "several fields are named addr_zip after renaming")]
+    fn test_rename_onto_globbed_field() {
+        make_select(r#"{"input":"people","select":["addr_*"],"rename":{"addr_city":"addr_zip"}}"#)
+            .select(&make_people());
+    }
+
+    #[test]
+    fn test_swapped_names() {
+        let table = make_select(
+            r#"{"input":"people","select":["id","name"],"rename":{"id":"name","name":"id"}}"#,
+        )
+        .select(&make_people());
+        assert_eq!(table.columns, vec!["name", "id"]);
+        assert_eq!(table.records[0].fields["name"], Variant::Int(1));
+    }
+}
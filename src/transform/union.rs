@@ -0,0 +1,230 @@
+use crate::register_process;
+use crate::state::{read_fields_list, Factory, Process, Record, State, Table, Variant};
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// Concatenates the `inputs` tables into a table named after the node. Every record gets the
+/// union of the fields of all the inputs, null when missing, plus the name of its table in
+/// `source_field` when configured. With `dedup_key` only the first (or the `last`, with
+/// `keep`) record of every key is kept.
+pub struct TransformUnion {
+    node_name: String,
+    inputs: Vec<String>,
+    source_field: Option<String>,
+    dedup_key: Vec<String>,
+    keep_last: bool,
+}
+
+impl TransformUnion {
+    // the indexes of the records to keep, records with a null in the key are always kept
+    fn deduplicate(&self, records: &[Record]) -> Vec<bool> {
+        let mut keep = vec![true; records.len()];
+        if self.dedup_key.is_empty() {
+            return keep;
+        }
+        let compare = |k1: &Vec<&Variant>, k2: &Vec<&Variant>| {
+            k1.iter()
+                .zip(k2.iter())
+                .map(|(v1, v2)| v1.compare(v2))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        };
+        let keys: Vec<(Vec<&Variant>, usize)> = records
+            .iter()
+            .enumerate()
+            .filter_map(|(index, record)| {
+                self.dedup_key
+                    .iter()
+                    .map(|f| record.fields.get(f).filter(|v| **v != Variant::Null))
+                    .collect::<Option<Vec<&Variant>>>()
+                    .map(|key| (key, index))
+            })
+            // the sort is stable, so the records of a key stay in their original order
+            .sorted_by(|(k1, _), (k2, _)| compare(k1, k2))
+            .collect();
+        for group in keys.chunk_by(|(k1, _), (k2, _)| compare(k1, k2) == Ordering::Equal) {
+            let kept = if self.keep_last {
+                group.last()
+            } else {
+                group.first()
+            };
+            for (_, index) in group {
+                keep[*index] = false;
+            }
+            keep[kept.unwrap().1] = true;
+        }
+        keep
+    }
+
+    pub fn union(&self, tables: &[&Table]) -> Table {
+        // the columns of the first table, then the new ones of the following tables
+        let mut fields: Vec<&str> = tables
+            .iter()
+            .flat_map(|t| t.column_names())
+            .unique()
+            .collect();
+        if let Some(source_field) = &self.source_field {
+            fields.retain(|f| f != source_field);
+        }
+        let records: Vec<Record> = tables
+            .iter()
+            .flat_map(|table| {
+                table.records.iter().map(|record| {
+                    let mut aligned = Record {
+                        fields: fields
+                            .iter()
+                            .map(|f| {
+                                let value = record.fields.get(*f).cloned();
+                                (f.to_string(), value.unwrap_or(Variant::Null))
+                            })
+                            .collect(),
+                    };
+                    if let Some(source_field) = &self.source_field {
+                        aligned
+                            .fields
+                            .insert(source_field.clone(), Variant::String(table.name.clone()));
+                    }
+                    aligned
+                })
+            })
+            .collect();
+        let keep = self.deduplicate(&records);
+        Table {
+            name: self.node_name.clone(),
+            records: records
+                .into_iter()
+                .zip(keep)
+                .filter(|(_, keep)| *keep)
+                .map(|(record, _)| record)
+                .collect(),
+            columns: fields
+                .iter()
+                .map(|f| f.to_string())
+                .chain(self.source_field.clone())
+                .collect(),
+            schema: None,
+        }
+    }
+}
+
+impl Process for TransformUnion {
+    register_process!(transform::union);
+    fn from_config(node_name: String, config: Map<String, Value>) -> Self {
+        let inputs = read_fields_list(&config, "inputs");
+        if inputs.is_empty() {
+            panic!("union needs at least one input");
+        }
+        let keep_last = match config
+            .get("keep")
+            .and_then(|k| k.as_str())
+            .unwrap_or("first")
+        {
+            "first" => false,
+            "last" => true,
+            keep => panic!("Unknown keep {}", keep),
+        };
+        TransformUnion {
+            node_name,
+            inputs,
+            source_field: config
+                .get("source_field")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_string()),
+            dedup_key: read_fields_list(&config, "dedup_key"),
+            keep_last,
+        }
+    }
+    fn run(&self, state: &mut State) {
+        let tables: Vec<&Table> = self
+            .inputs
+            .iter()
+            .map(|name| {
+                state
+                    .find_table(name)
+                    .unwrap_or_else(|| panic!("table {} not found", name))
+            })
+            .collect();
+        let union = self.union(&tables);
+        state.tables.push(union);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn make_table(name: &str, rows: &[(i64, Option<&str>)]) -> Table {
+        Table {
+            name: name.to_string(),
+            records: rows
+                .iter()
+                .map(|(id, email)| {
+                    let mut fields = IndexMap::new();
+                    fields.insert("id".to_string(), Variant::Int(*id));
+                    if let Some(email) = email {
+                        fields.insert("email".to_string(), Variant::String(email.to_string()));
+                    }
+                    Record { fields }
+                })
+                .collect(),
+            columns: vec![],
+            schema: None,
+        }
+    }
+
+    fn make_union(config: &str) -> TransformUnion {
+        let config: Value = serde_json::from_str(config).unwrap();
+        TransformUnion::from_config("all".to_string(), config.as_object().unwrap().clone())
+    }
+
+    #[test]
+    fn test_union_with_source_and_alignment() {
+        let tenant1 = make_table("tenant1", &[(1, Some("a@x")), (2, Some("b@x"))]);
+        let tenant2 = make_table("tenant2", &[(2, None)]);
+        let union = make_union(r#"{"inputs":["tenant1","tenant2"],"source_field":"tenant"}"#)
+            .union(&[&tenant1, &tenant2]);
+        assert_eq!(union.name, "all");
+        assert_eq!(union.records.len(), 3);
+        let last = &union.records[2].fields;
+        assert_eq!(last.len(), 3);
+        assert_eq!(last["id"], Variant::Int(2));
+        assert_eq!(last["email"], Variant::Null);
+        assert_eq!(last["tenant"], Variant::String("tenant2".to_string()));
+    }
+
+    #[test]
+    fn test_dedup_on_key() {
+        let tenant1 = make_table("tenant1", &[(1, Some("a@x")), (2, Some("b@x"))]);
+        let tenant2 = make_table("tenant2", &[(2, Some("b@y")), (3, None)]);
+        let ids = |table: &Table| {
+            table
+                .records
+                .iter()
+                .map(|r| (r.fields["id"].clone(), r.fields["email"].clone()))
+                .collect::<Vec<(Variant, Variant)>>()
+        };
+        let first = make_union(r#"{"inputs":["tenant1","tenant2"],"dedup_key":"id"}"#)
+            .union(&[&tenant1, &tenant2]);
+        assert_eq!(
+            ids(&first),
+            vec![
+                (Variant::Int(1), Variant::String("a@x".to_string())),
+                (Variant::Int(2), Variant::String("b@x".to_string())),
+                (Variant::Int(3), Variant::Null),
+            ]
+        );
+        let last =
+            make_union(r#"{"inputs":["tenant1","tenant2"],"dedup_key":["id"],"keep":"last"}"#)
+                .union(&[&tenant1, &tenant2]);
+        assert_eq!(
+            ids(&last)[1],
+            (Variant::Int(2), Variant::String("b@y".to_string()))
+        );
+        // records with a null key are never duplicates
+        let by_email = make_union(r#"{"inputs":["tenant2","tenant2"],"dedup_key":"email"}"#)
+            .union(&[&tenant2, &tenant2]);
+        assert_eq!(by_email.records.len(), 3);
+    }
+}